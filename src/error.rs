@@ -0,0 +1,491 @@
+use std::{
+    fmt,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::graph::UniformMismatch;
+
+/// A region of a graph file, as `(line, column)` pairs.
+/// Lines are 1-based, columns are 0-based byte offsets into
+/// the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: (usize, usize),
+    pub end:   (usize, usize),
+}
+
+/// Everything that can go wrong while loading, building, or
+/// reloading a shader graph. Match on this to tell a typo
+/// in the graph apart from a broken shader.
+#[derive(Debug)]
+pub enum Error {
+    /// The graph's lisp source could not be parsed.
+    Parse(String),
+    /// The lisp parsed, but a form was malformed, e.g. a
+    /// symbol was expected but not found.
+    Syntax(String),
+    /// A variable or function was referenced before being
+    /// defined.
+    Undefined(String),
+    /// A `(shader ...)` form named a shader that was not
+    /// loaded.
+    UnknownShader(String),
+    /// A `(shader ...)` form named a library, which can
+    /// only be `#include`d.
+    LibraryShader(String),
+    /// An `(extern ...)` form named a function that was not
+    /// provided.
+    UnknownExternal(String),
+    /// An external function failed while adding its
+    /// subgraph.
+    External { name: String, error: Box<Error> },
+    /// A shader failed to compile or link. `shader` is the
+    /// shader's name, if known.
+    ShaderCompile {
+        shader: Option<String>,
+        log:    String,
+    },
+    /// A value had a different type than the one expected.
+    TypeMismatch {
+        expected: &'static str,
+        found:    String,
+    },
+    /// A function was called with the wrong number of
+    /// arguments.
+    Arity {
+        function: String,
+        expected: usize,
+        found:    usize,
+    },
+    /// An argument could not be bound to a parameter of a
+    /// function.
+    Argument {
+        function: String,
+        name:     String,
+        reason:   &'static str,
+    },
+    /// A file or directory could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// A file name could not be turned into a shader name.
+    InvalidPath(PathBuf),
+    /// An `#include` directive was not of the form
+    /// `#include "name"`.
+    MalformedInclude { file: String, line: usize },
+    /// An `#include` directive named a shader that was not
+    /// loaded.
+    UnknownInclude {
+        file:    String,
+        line:    usize,
+        include: String,
+    },
+    /// Shaders include each other. Lists the files
+    /// included, ending with the first file again.
+    IncludeCycle(Vec<String>),
+    /// A texture format name was not recognized.
+    UnknownFormat(String),
+    /// A texture format can not be rendered to with the
+    /// current context.
+    UnsupportedFormat(String),
+    /// A shader's input, numbered from 0, was sampled with
+    /// a mipmapped filter, but its node does not
+    /// generate mipmaps.
+    MissingMipmaps { input: usize },
+    /// A parameter was given the name of a uniform the
+    /// graph already provides, e.g. `time`.
+    ReservedParam(String),
+    /// A shader declared a uniform with a different type
+    /// than the graph passes it.
+    Uniform(UniformMismatch),
+    /// A forward pass was not given a texture for one of
+    /// the graph's inputs. Unnamed inputs are called
+    /// `input-N`, by their position.
+    MissingInput(String),
+    /// An input could not be bound to the uniform
+    /// `u_<name>`.
+    InputName {
+        name:   String,
+        reason: &'static str,
+    },
+    /// Profiling was enabled, but the context does not
+    /// support timer queries.
+    UnsupportedProfiling,
+    /// A rendering context could not be created.
+    Context(String),
+    /// A texture could not be created.
+    Texture(String),
+    /// An error raised while evaluating a specific form in
+    /// a graph file.
+    Form {
+        error: Box<Error>,
+        span:  Span,
+        /// The user `define`s being evaluated when the
+        /// error occurred, innermost first, with
+        /// where each was called from.
+        calls: Vec<(String, Span)>,
+        /// The graph file, if the graph was loaded from
+        /// one.
+        file:  Option<PathBuf>,
+        /// The source line the span starts on.
+        line:  Option<String>,
+    },
+}
+
+impl Error {
+    /// Attaches the location of the form that caused this
+    /// error. Errors that already have a location keep the
+    /// innermost one.
+    pub fn at(self, span: Span) -> Error {
+        match self {
+            located @ Error::Form { .. } => located,
+            error => Error::Form {
+                error: Box::new(error),
+                span,
+                calls: vec![],
+                file: None,
+                line: None,
+            },
+        }
+    }
+
+    /// Records that this error happened inside a call to
+    /// the user-defined function `function`, called at
+    /// `span`.
+    pub fn called_from(self, function: &str, span: Span) -> Error {
+        match self.at(span) {
+            Error::Form {
+                error,
+                span: inner,
+                mut calls,
+                file,
+                line,
+            } => {
+                calls.push((function.to_string(), span));
+                Error::Form {
+                    error,
+                    span: inner,
+                    calls,
+                    file,
+                    line,
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Fills in the graph file and source text needed to
+    /// render a located error as a diagnostic.
+    pub fn with_source(self, path: Option<&Path>, source: &str) -> Error {
+        match self {
+            Error::Form {
+                error, span, calls, ..
+            } => Error::Form {
+                error,
+                span,
+                calls,
+                file: path.map(Path::to_path_buf),
+                line: span
+                    .start
+                    .0
+                    .checked_sub(1)
+                    .and_then(|index| source.lines().nth(index))
+                    .map(str::to_string),
+            },
+            other => other,
+        }
+    }
+}
+
+/// Writes a rustc-style diagnostic for an error located at
+/// `span`, underlining the offending form.
+fn diagnostic(
+    f: &mut fmt::Formatter<'_>,
+    error: &Error,
+    span: &Span,
+    calls: &[(String, Span)],
+    file: &str,
+    line: Option<&str>,
+) -> fmt::Result {
+    let (line_no, column) = span.start;
+    let gutter = " ".repeat(line_no.to_string().len());
+
+    writeln!(f, "error: {}", error)?;
+    write!(f, "{}--> {}:{}:{}", gutter, file, line_no, column + 1)?;
+
+    if let Some(line) = line {
+        // forms that span multiple lines are underlined to the
+        // end of the first line
+        let end = if span.end.0 == line_no {
+            span.end.1.min(line.len())
+        } else {
+            line.len()
+        };
+        let underline = "^".repeat(end.saturating_sub(column).max(1));
+
+        writeln!(f)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_no, line)?;
+        write!(f, "{} | {}{}", gutter, " ".repeat(column), underline)?;
+    }
+
+    for (function, span) in calls {
+        let (line_no, column) = span.start;
+        writeln!(f)?;
+        write!(
+            f,
+            "{} = note: in call to `{}` at {}:{}:{}",
+            gutter,
+            function,
+            file,
+            line_no,
+            column + 1
+        )?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(message) => {
+                write!(f, "Could not parse graph: {}", message)
+            },
+            Error::Syntax(message) => write!(f, "{}", message),
+            Error::Undefined(name) => {
+                write!(f, "Item `{}` is not defined", name)
+            },
+            Error::UnknownShader(name) => {
+                write!(f, "Could not load shader `{}`, it is not defined", name)
+            },
+            Error::LibraryShader(name) => write!(
+                f,
+                "Could not load shader `{}`, `{}.glsl` is a library, \
+                 which can only be included by other shaders",
+                name, name
+            ),
+            Error::UnknownExternal(name) => write!(
+                f,
+                "Could not load external function `{}`, it is not defined",
+                name
+            ),
+            Error::External { name, error } => write!(
+                f,
+                "While adding external function `{}`: {}",
+                name, error
+            ),
+            Error::ShaderCompile {
+                shader: Some(shader),
+                log,
+            } => write!(f, "Could not compile shader `{}`:\n{}", shader, log),
+            Error::ShaderCompile { shader: None, log } => {
+                write!(f, "Could not compile shader:\n{}", log)
+            },
+            Error::TypeMismatch { expected, found } => write!(
+                f,
+                "Type mismatch: expected a {}, found `{}`",
+                expected, found
+            ),
+            Error::Arity {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{}` expected {} args, but found {}",
+                function, expected, found
+            ),
+            Error::Argument {
+                function,
+                name,
+                reason,
+            } => write!(f, "Argument `{}` of `{}` {}", name, function, reason),
+            Error::Io { path, error } => {
+                write!(f, "Could not read `{}`: {}", path.display(), error)
+            },
+            Error::InvalidPath(path) => write!(
+                f,
+                "Could not infer shader name from `{}`",
+                path.display()
+            ),
+            Error::MalformedInclude { file, line } => write!(
+                f,
+                "Malformed include at {}:{}, expected `#include \"name\"`",
+                file, line
+            ),
+            Error::UnknownInclude {
+                file,
+                line,
+                include,
+            } => write!(
+                f,
+                "Could not include shader `{}` at {}:{}, it is not defined",
+                include, file, line
+            ),
+            Error::IncludeCycle(files) => {
+                write!(f, "Shaders include each other: {}", files.join(" -> "))
+            },
+            Error::UnknownFormat(name) => write!(
+                f,
+                "Unknown texture format `{}`, expected e.g. `rgba16` or `r32f`",
+                name
+            ),
+            Error::UnsupportedFormat(name) => write!(
+                f,
+                "Texture format `{}` can not be rendered to on this device",
+                name
+            ),
+            Error::MissingMipmaps { input } => write!(
+                f,
+                "Input {} is sampled using mipmaps, \
+                 but the node it comes from does not generate them",
+                input
+            ),
+            Error::ReservedParam(name) => write!(
+                f,
+                "Can not declare parameter `{}`, \
+                 the uniform `u_{}` is already provided",
+                name, name
+            ),
+            Error::Uniform(mismatch) => write!(f, "{}", mismatch),
+            Error::MissingInput(name) => {
+                write!(
+                    f,
+                    "No texture was passed for the graph input `{}`",
+                    name
+                )
+            },
+            Error::InputName { name, reason } => {
+                write!(f, "Can not bind an input to `u_{}`, {}", name, reason)
+            },
+            Error::UnsupportedProfiling => write!(
+                f,
+                "Can not profile the graph, timer queries are not supported"
+            ),
+            Error::Context(message) => write!(f, "{}", message),
+            Error::Texture(message) => {
+                write!(f, "Unable to create texture: {}", message)
+            },
+            Error::Form {
+                error,
+                span,
+                calls,
+                file,
+                line,
+            } => {
+                let file = file
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "<graph>".to_string());
+                diagnostic(f, error, span, calls, &file, line.as_deref())
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::External { error, .. } => Some(error.as_ref()),
+            Error::Io { error, .. } => Some(error),
+            Error::Form { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: (usize, usize), end: (usize, usize)) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn underlines_the_form() {
+        let source = "(let a 1)\n(output (shader \"blur\" 1 2))";
+        let error = Error::Undefined("x".to_string())
+            .at(span((2, 8), (2, 28)))
+            .with_source(Some(Path::new("shader.graph")), source);
+        assert_eq!(
+            error.to_string(),
+            "error: Item `x` is not defined\n\
+             \x20--> shader.graph:2:9\n\
+             \x20 |\n\
+             2 | (output (shader \"blur\" 1 2))\n\
+             \x20 |         ^^^^^^^^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn multi_line_forms_are_underlined_to_the_end_of_the_line() {
+        let source = "(define (f)\n  (shader \"a\" 1 1))";
+        let error = Error::Syntax("bad".to_string())
+            .at(span((1, 8), (2, 18)))
+            .with_source(None, source);
+        assert_eq!(
+            error.to_string(),
+            "error: bad\n\
+             \x20--> <graph>:1:9\n\
+             \x20 |\n\
+             1 | (define (f)\n\
+             \x20 |         ^^^"
+        );
+    }
+
+    #[test]
+    fn underline_is_clamped_to_the_line() {
+        let error = Error::Syntax("bad".to_string())
+            .at(span((1, 4), (1, 40)))
+            .with_source(None, "(a b)");
+        assert!(error.to_string().ends_with("1 | (a b)\n  |     ^"));
+
+        // an empty span still gets a caret
+        let error = Error::Syntax("bad".to_string())
+            .at(span((1, 2), (1, 2)))
+            .with_source(None, "(a b)");
+        assert!(error.to_string().ends_with("  |   ^"));
+    }
+
+    #[test]
+    fn calls_are_noted_innermost_first() {
+        let source = "(define (f) (g))\n(define (g) x)\n(let y (f))";
+        let error = Error::Undefined("x".to_string())
+            .at(span((2, 12), (2, 13)))
+            .called_from("g", span((1, 12), (1, 15)))
+            .called_from("f", span((3, 7), (3, 10)))
+            .with_source(None, source);
+        assert_eq!(
+            error.to_string(),
+            "error: Item `x` is not defined\n\
+             \x20--> <graph>:2:13\n\
+             \x20 |\n\
+             2 | (define (g) x)\n\
+             \x20 |             ^\n\
+             \x20 = note: in call to `g` at <graph>:1:13\n\
+             \x20 = note: in call to `f` at <graph>:3:8"
+        );
+    }
+
+    #[test]
+    fn innermost_location_is_kept() {
+        let error = Error::Syntax("bad".to_string())
+            .at(span((2, 0), (2, 1)))
+            .at(span((1, 0), (1, 1)));
+        assert!(
+            matches!(error, Error::Form { span, .. } if span.start == (2, 0))
+        );
+    }
+
+    #[test]
+    fn span_without_a_source_line() {
+        let error = Error::Syntax("bad".to_string())
+            .at(span((0, 0), (0, 0)))
+            .with_source(None, "(a b)");
+        assert_eq!(error.to_string(), "error: bad\n --> <graph>:0:1");
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+
+use crate::{
+    glsl::{
+        source::directive,
+        Origin,
+        ShaderSource,
+    },
+    Error,
+};
+
+/// The file a shader was loaded from, used to refer to it
+/// in errors.
+pub fn file_name(shader: &str) -> String { format!("{}.frag", shader) }
+
+/// The file a library was loaded from.
+pub fn library_file_name(library: &str) -> String {
+    format!("{}.glsl", library)
+}
+
+/// Builds the source of a shader from maps of shader and
+/// library names to their source, replacing each
+/// `#include "name"` directive with the source of the
+/// library or shader `name`. Names are looked up relative
+/// to the including file first, then from the root of the
+/// shader directory. If a name leaves off the extension,
+/// libraries (`.glsl`) are preferred to shaders (`.frag`).
+/// Each file is only included once, so a file doesn't need
+/// include guards, and files that include each other are
+/// an error. The `#version` of an included file is left
+/// out, as only the shader's own may come first.
+pub fn preprocess(
+    name: &str,
+    shaders: &BTreeMap<String, String>,
+    libraries: &BTreeMap<String, String>,
+) -> Result<ShaderSource, Error> {
+    if !shaders.contains_key(name) {
+        return Err(if libraries.contains_key(name) {
+            Error::LibraryShader(name.to_string())
+        } else {
+            Error::UnknownShader(name.to_string())
+        });
+    }
+
+    let mut preprocessor = Preprocessor {
+        shaders,
+        libraries,
+        stack: vec![],
+        included: BTreeSet::new(),
+        lines: vec![],
+    };
+    let file = file_name(name);
+    preprocessor.include(&file)?;
+    Ok(ShaderSource::from_lines(&file, preprocessor.lines))
+}
+
+struct Preprocessor<'a> {
+    shaders:   &'a BTreeMap<String, String>,
+    libraries: &'a BTreeMap<String, String>,
+    /// The files being included, outermost first.
+    stack:     Vec<String>,
+    included:  BTreeSet<String>,
+    lines:     Vec<(String, Option<Origin>)>,
+}
+
+impl<'a> Preprocessor<'a> {
+    /// The source of a file, by its name with extension.
+    fn source(&self, file: &str) -> Option<&'a str> {
+        let (shaders, libraries) = (self.shaders, self.libraries);
+        if let Some(name) = file.strip_suffix(".frag") {
+            shaders.get(name).map(String::as_str)
+        } else if let Some(name) = file.strip_suffix(".glsl") {
+            libraries.get(name).map(String::as_str)
+        } else {
+            None
+        }
+    }
+
+    fn include(&mut self, file: &str) -> Result<(), Error> {
+        if let Some(start) = self.stack.iter().position(|f| f == file) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(file.to_string());
+            return Err(Error::IncludeCycle(cycle));
+        }
+        if !self.included.insert(file.to_string()) {
+            return Ok(());
+        }
+
+        // unwrap: only files that were found are included
+        let source = self.source(file).unwrap();
+        self.stack.push(file.to_string());
+        for (index, line) in source.lines().enumerate() {
+            let origin = Origin {
+                file: file.to_string(),
+                line: index + 1,
+            };
+            let path = match directive(line) {
+                Some(("include", path)) => path,
+                Some(("version", _)) if self.stack.len() > 1 => continue,
+                _ => {
+                    self.lines.push((line.to_string(), Some(origin)));
+                    continue;
+                },
+            };
+
+            let path =
+                include_path(path).ok_or_else(|| Error::MalformedInclude {
+                    file: origin.file.clone(),
+                    line: origin.line,
+                })?;
+            let included = self.resolve(file, path).ok_or_else(|| {
+                Error::UnknownInclude {
+                    file:    origin.file.clone(),
+                    line:    origin.line,
+                    include: path.to_string(),
+                }
+            })?;
+            self.include(&included)?;
+        }
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    /// Finds the file an include in `from` refers to.
+    fn resolve(&self, from: &str, path: &str) -> Option<String> {
+        let relative = from
+            .rsplit_once('/')
+            .map(|(dir, _)| format!("{}/{}", dir, path));
+        let has_extension = path.ends_with(".frag") || path.ends_with(".glsl");
+
+        relative
+            .into_iter()
+            .chain(std::iter::once(path.to_string()))
+            .filter_map(|candidate| normalize(&candidate))
+            .flat_map(|candidate| {
+                if has_extension {
+                    vec![candidate]
+                } else {
+                    vec![library_file_name(&candidate), file_name(&candidate)]
+                }
+            })
+            .find(|candidate| self.source(candidate).is_some())
+    }
+}
+
+/// Takes the quoted path out of the rest of an include
+/// directive, e.g. `"noise" // 2d only`.
+fn include_path(rest: &str) -> Option<&str> {
+    let rest = rest.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    let after = rest[end + 1..].trim();
+    if after.is_empty() || after.starts_with("//") {
+        Some(&rest[..end])
+    } else {
+        None
+    }
+}
+
+/// Resolves `.` and `..` in a file name, returning `None`
+/// if it leaves the shader directory.
+fn normalize(name: &str) -> Option<String> {
+    let mut parts = vec![];
+    for part in name.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop()?;
+            },
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(files: &[(&str, &str)]) -> BTreeMap<String, String> {
+        files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    /// The lines of the preprocessed shader, without the
+    /// `#line` directives added to trace them.
+    fn lines(
+        name: &str,
+        shaders: &[(&str, &str)],
+        libraries: &[(&str, &str)],
+    ) -> Result<Vec<String>, Error> {
+        let source = preprocess(name, &files(shaders), &files(libraries))?;
+        Ok(source
+            .text()
+            .lines()
+            .filter(|line| !matches!(directive(line), Some(("line", _))))
+            .map(str::to_string)
+            .collect())
+    }
+
+    #[test]
+    fn includes_relative_to_the_including_file_first() {
+        let shaders = [("effects/main", "#include \"noise\"\nmain")];
+        let libraries = [("noise", "root"), ("effects/noise", "relative")];
+        assert_eq!(lines("effects/main", &shaders, &libraries).unwrap(), [
+            "relative", "main"
+        ]);
+    }
+
+    #[test]
+    fn includes_from_the_root() {
+        let shaders = [("effects/main", "#include \"common\"\nmain")];
+        let libraries = [("common", "root")];
+        assert_eq!(lines("effects/main", &shaders, &libraries).unwrap(), [
+            "root", "main"
+        ]);
+    }
+
+    #[test]
+    fn prefers_libraries_without_an_extension() {
+        let shaders = [
+            ("main", "#include \"noise\"\n#include \"noise.frag\""),
+            ("noise", "shader"),
+        ];
+        let libraries = [("noise", "library")];
+        assert_eq!(lines("main", &shaders, &libraries).unwrap(), [
+            "library", "shader"
+        ]);
+    }
+
+    #[test]
+    fn normalizes_parent_directories() {
+        let shaders = [("effects/blur/gauss", "#include \"../noise\"")];
+        let libraries = [("effects/noise", "noise")];
+        assert_eq!(
+            lines("effects/blur/gauss", &shaders, &libraries).unwrap(),
+            ["noise"]
+        );
+        assert_eq!(normalize("a/./b/../c"), Some("a/c".to_string()));
+        assert_eq!(normalize("a/../../c"), None);
+    }
+
+    #[test]
+    fn can_not_include_outside_the_shader_directory() {
+        let shaders = [("main", "#include \"../noise\"")];
+        let libraries = [("noise", "noise")];
+        assert!(matches!(
+            lines("main", &shaders, &libraries),
+            Err(Error::UnknownInclude { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn self_include_is_a_cycle() {
+        let shaders = [("main", "#include \"main.frag\"")];
+        match lines("main", &shaders, &[]) {
+            Err(Error::IncludeCycle(cycle)) => {
+                assert_eq!(cycle, ["main.frag", "main.frag"])
+            },
+            other => panic!("expected a cycle, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mutual_include_is_a_cycle() {
+        let shaders = [("main", "#include \"a\"")];
+        let libraries = [("a", "#include \"b\""), ("b", "#include \"a\"")];
+        match lines("main", &shaders, &libraries) {
+            Err(Error::IncludeCycle(cycle)) => {
+                assert_eq!(cycle, ["a.glsl", "b.glsl", "a.glsl"])
+            },
+            other => panic!("expected a cycle, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let shaders = [("main", "#include \"a\"\n#include \"b\"\nmain")];
+        let libraries = [
+            ("a", "#include \"common\"\na"),
+            ("b", "#include \"common\"\nb"),
+            ("common", "common"),
+        ];
+        assert_eq!(lines("main", &shaders, &libraries).unwrap(), [
+            "common", "a", "b", "main"
+        ]);
+    }
+
+    #[test]
+    fn included_version_is_left_out() {
+        let shaders = [
+            ("main", "#version 330\n#include \"other.frag\"\nmain"),
+            ("other", "#version 330\nother"),
+        ];
+        assert_eq!(lines("main", &shaders, &[]).unwrap(), [
+            "#version 330",
+            "other",
+            "main"
+        ]);
+    }
+
+    #[test]
+    fn malformed_include() {
+        let shaders = [("main", "main\n#include noise")];
+        assert!(matches!(
+            lines("main", &shaders, &[]),
+            Err(Error::MalformedInclude { line: 2, .. })
+        ));
+        assert_eq!(include_path("\"noise\" // 2d only"), Some("noise"));
+        assert_eq!(include_path("\"noise\" extra"), None);
+    }
+}
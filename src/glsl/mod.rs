@@ -0,0 +1,12 @@
+mod include;
+mod source;
+
+pub use include::{
+    file_name,
+    library_file_name,
+    preprocess,
+};
+pub use source::{
+    Origin,
+    ShaderSource,
+};
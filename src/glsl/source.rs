@@ -0,0 +1,426 @@
+/// Where a line of generated GLSL came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The file the line was written in, e.g. `blur.frag`.
+    pub file: String,
+    /// The 1-based line number within that file.
+    pub line: usize,
+}
+
+/// GLSL source that remembers which file and line each of
+/// its lines came from. Shaders are rewritten before
+/// they're compiled (e.g. by `shader-param` substitutions),
+/// so this is used to point compile errors back at the
+/// original file.
+#[derive(Debug, Clone)]
+pub struct ShaderSource {
+    name:  Option<String>,
+    lines: Vec<(String, Option<Origin>)>,
+}
+
+impl ShaderSource {
+    /// Creates a new source from the contents of a file.
+    /// `name` is used to refer to the shader in errors.
+    pub fn new(name: &str, text: &str) -> ShaderSource {
+        let lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                let origin = Origin {
+                    file: name.to_string(),
+                    line: index + 1,
+                };
+                (line.to_string(), Some(origin))
+            })
+            .collect();
+
+        ShaderSource {
+            name: Some(name.to_string()),
+            lines,
+        }
+    }
+
+    /// Creates a source from lines that were already traced
+    /// back to where they came from.
+    pub(super) fn from_lines(
+        name: &str,
+        lines: Vec<(String, Option<Origin>)>,
+    ) -> ShaderSource {
+        ShaderSource {
+            name: Some(name.to_string()),
+            lines,
+        }
+    }
+
+    /// The name of the shader, if it has one.
+    pub fn name(&self) -> Option<&str> { self.name.as_deref() }
+
+    /// Replaces all occurences of `pattern` with
+    /// `replacement`. If the replacement spans several
+    /// lines, they all map back to the line the pattern was
+    /// found on.
+    pub fn replace(&mut self, pattern: &str, replacement: &str) {
+        let mut lines = Vec::with_capacity(self.lines.len());
+        for (text, origin) in self.lines.drain(..) {
+            if !text.contains(pattern) {
+                lines.push((text, origin));
+                continue;
+            }
+
+            for replaced in text.replace(pattern, replacement).split('\n') {
+                lines.push((replaced.to_string(), origin.clone()));
+            }
+        }
+        self.lines = lines;
+    }
+
+    /// Declares uniforms, given as `(name, glsl type)`,
+    /// after `#version` and any `#extension`s, skipping
+    /// those already declared.
+    pub fn declare_uniforms(&mut self, uniforms: &[(String, &str)]) {
+        let declares = |name: &str| {
+            self.lines.iter().any(|(line, _)| {
+                let words: Vec<&str> = line
+                    .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .collect();
+                words.contains(&"uniform") && words.contains(&name)
+            })
+        };
+        let declarations: Vec<(String, Option<Origin>)> = uniforms
+            .iter()
+            .filter(|(name, _)| !declares(name))
+            .map(|(name, glsl_type)| {
+                (format!("uniform {} {};", glsl_type, name), None)
+            })
+            .collect();
+
+        let start = self
+            .lines
+            .iter()
+            .rposition(|(line, _)| {
+                matches!(directive(line), Some(("version" | "extension", _)))
+            })
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        self.lines.splice(start..start, declarations);
+    }
+
+    /// The files the lines came from, in the order they
+    /// first appear.
+    fn files(&self) -> Vec<&str> {
+        let mut files = vec![];
+        for origin in
+            self.lines.iter().filter_map(|(_, origin)| origin.as_ref())
+        {
+            if !files.contains(&origin.file.as_str()) {
+                files.push(origin.file.as_str());
+            }
+        }
+        files
+    }
+
+    /// Lines of the `n`th file are numbered from `n` times
+    /// this, as not every driver reports which file an
+    /// error is in. Lines without an origin come after the
+    /// last file, numbered as in the source.
+    fn line_offset(&self) -> usize {
+        let longest = self
+            .lines
+            .iter()
+            .filter_map(|(_, origin)| origin.as_ref().map(|o| o.line))
+            .chain(std::iter::once(self.lines.len()))
+            .max()
+            .unwrap_or(0);
+        let mut offset = 10;
+        while offset <= longest {
+            offset *= 10;
+        }
+        offset
+    }
+
+    /// Returns the origin of a line, numbered as by the
+    /// compiler, i.e. after the `#line` directives added
+    /// by `text`.
+    pub fn origin(&self, line: usize) -> Option<Origin> {
+        let files = self.files();
+        let offset = self.line_offset();
+        let (index, line) = (line / offset, line % offset);
+        match files.get(index) {
+            Some(file) => Some(Origin {
+                file: file.to_string(),
+                line,
+            }),
+            None if index == files.len() => {
+                self.lines.get(line.checked_sub(1)?)?.1.clone()
+            },
+            None => None,
+        }
+    }
+
+    /// The generated source, as passed to the compiler.
+    /// Wherever a line doesn't follow on from the one
+    /// before it in the same file, e.g. around an
+    /// included file, a `#line` directive is added, so
+    /// that the compiler counts lines as in the original
+    /// files.
+    pub fn text(&self) -> String {
+        let files = self.files();
+        let offset = self.line_offset();
+        let version = self.lines.iter().position(|(line, _)| {
+            directive(line).map(|(name, _)| name) == Some("version")
+        });
+        // before GLSL 3.30, `#line n` numbers the line after
+        // it `n + 1`
+        let adjust = match version.map(|index| &self.lines[index].0) {
+            Some(line) => {
+                let (_, rest) = directive(line).unwrap();
+                let mut words = rest.split_whitespace();
+                let number: Option<u32> =
+                    words.next().and_then(|n| n.parse().ok());
+                let es = words.next() == Some("es");
+                match number {
+                    Some(number) if number >= 330 || (es && number >= 300) => 0,
+                    _ => 1,
+                }
+            },
+            None => 1,
+        };
+
+        let mut text = String::new();
+        // the number the compiler will give the next line
+        let mut next = 1;
+        for (index, (line, origin)) in self.lines.iter().enumerate() {
+            let (file, number) = match origin {
+                Some(origin) => {
+                    // unwrap: `files` has every file with a line
+                    let file = files
+                        .iter()
+                        .position(|file| *file == origin.file)
+                        .unwrap();
+                    (file, file * offset + origin.line)
+                },
+                None => (files.len(), files.len() * offset + index + 1),
+            };
+
+            // nothing but comments may come before `#version`
+            let allowed =
+                version.map(|version| index > version).unwrap_or(true);
+            if !files.is_empty() && allowed && number != next {
+                text.push_str(&format!("#line {} {}\n", number - adjust, file));
+                next = number;
+            }
+            text.push_str(line);
+            text.push('\n');
+            next += 1;
+
+            // a directive in a branch that isn't compiled is
+            // ignored, so restate the line after a branch
+            if let Some(("else" | "elif" | "endif", _)) = directive(line) {
+                next = 0;
+            }
+        }
+        text
+    }
+
+    /// Rewrites the locations in a driver's compile log,
+    /// e.g. `0:12(5): error: ...`, to point at the
+    /// original file and line. Lines of the log that
+    /// don't start with a location are left as is.
+    pub fn remap_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| {
+                self.remap_log_line(line)
+                    .unwrap_or_else(|| line.to_string())
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn remap_log_line(&self, line: &str) -> Option<String> {
+        // drivers disagree on the format, but it's usually
+        // `[ERROR: ]<string>:<line>` or `<string>(<line>)`
+        let start = line.find(|c: char| c.is_ascii_digit())?;
+        let prefix = &line[..start];
+        if !prefix
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c == ':' || c == ' ')
+        {
+            return None;
+        }
+
+        let rest = &line[start..];
+        let string_end = rest.find(|c: char| !c.is_ascii_digit())?;
+        let separator = rest[string_end..].chars().next()?;
+        let after = &rest[string_end + separator.len_utf8()..];
+        let number_end = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let number = after[..number_end].parse::<usize>().ok()?;
+        let remainder = match separator {
+            ':' => &after[number_end..],
+            '(' => after[number_end..].strip_prefix(')')?,
+            _ => return None,
+        };
+
+        let origin = self.origin(number)?;
+        Some(format!(
+            "{}{}:{}{}",
+            prefix, origin.file, origin.line, remainder
+        ))
+    }
+}
+
+impl From<&str> for ShaderSource {
+    /// Creates an anonymous source. Its lines have no
+    /// origin, so compile errors are reported as-is.
+    fn from(text: &str) -> ShaderSource {
+        ShaderSource {
+            name:  None,
+            lines: text.lines().map(|l| (l.to_string(), None)).collect(),
+        }
+    }
+}
+
+/// Splits a preprocessor directive into its name and the
+/// rest of the line, e.g. `("include", "\"noise\"")`.
+pub(super) fn directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(rest.len());
+    Some((&rest[..end], rest[end..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers the lines of `text` as a compiler would,
+    /// following its `#line` directives.
+    fn compiled_lines(text: &str, before_330: bool) -> Vec<(usize, &str)> {
+        let mut lines = vec![];
+        let mut number = 1;
+        for line in text.lines() {
+            match directive(line) {
+                Some(("line", rest)) => {
+                    let next: usize = rest
+                        .split_whitespace()
+                        .next()
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    number = if before_330 { next + 1 } else { next };
+                },
+                _ => {
+                    lines.push((number, line));
+                    number += 1;
+                },
+            }
+        }
+        lines
+    }
+
+    fn origin(file: &str, line: usize) -> Option<Origin> {
+        Some(Origin {
+            file: file.to_string(),
+            line,
+        })
+    }
+
+    fn included_source(version: &str) -> ShaderSource {
+        ShaderSource::from_lines("main.frag", vec![
+            (version.to_string(), origin("main.frag", 1)),
+            ("float noise() {".to_string(), origin("noise.glsl", 1)),
+            ("}".to_string(), origin("noise.glsl", 2)),
+            ("void main() {".to_string(), origin("main.frag", 3)),
+            ("}".to_string(), origin("main.frag", 4)),
+        ])
+    }
+
+    #[test]
+    fn text_without_changes_has_no_line_directives() {
+        let source =
+            ShaderSource::new("a.frag", "#version 140\nvoid main() {}");
+        assert_eq!(source.text(), "#version 140\nvoid main() {}\n");
+        assert_eq!(source.origin(2), origin("a.frag", 2));
+    }
+
+    /// Checks the compiler would trace each line of
+    /// `source` back to where it came from.
+    fn assert_traced(source: &ShaderSource, before_330: bool) {
+        let text = source.text();
+        let compiled = compiled_lines(&text, before_330);
+        assert_eq!(compiled.len(), source.lines.len(), "{}", text);
+        for ((number, _), (_, expected)) in compiled.iter().zip(&source.lines) {
+            assert_eq!(&source.origin(*number), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn line_directives_before_330() {
+        let source = included_source("#version 140");
+        assert!(source.text().contains("#line 10 1\n"));
+        assert_traced(&source, true);
+    }
+
+    #[test]
+    fn line_directives_from_330() {
+        for version in ["#version 330", "#version 300 es"] {
+            let source = included_source(version);
+            assert!(source.text().contains("#line 11 1\n"));
+            assert_traced(&source, false);
+        }
+    }
+
+    #[test]
+    fn origin_of_each_file() {
+        let source = included_source("#version 330");
+        assert_eq!(source.origin(1), origin("main.frag", 1));
+        assert_eq!(source.origin(12), origin("noise.glsl", 2));
+        assert_eq!(source.origin(4), origin("main.frag", 4));
+        assert_eq!(source.origin(35), None);
+    }
+
+    #[test]
+    fn replaced_lines_map_to_the_original_line() {
+        let mut source =
+            ShaderSource::new("a.frag", "#version 140\n<body>\nvoid main() {}");
+        source.replace("<body>", "float a;\nfloat b;");
+        let text = source.text();
+        let numbers: Vec<usize> = compiled_lines(&text, true)
+            .iter()
+            .map(|(n, _)| *n)
+            .collect();
+        assert_eq!(numbers, [1, 2, 2, 3]);
+        assert_eq!(source.origin(2), origin("a.frag", 2));
+    }
+
+    #[test]
+    fn remap_mesa_log() {
+        let source = included_source("#version 330");
+        assert_eq!(
+            source.remap_log("0:12(3): error: `x' undeclared"),
+            "noise.glsl:2(3): error: `x' undeclared"
+        );
+        assert_eq!(
+            source.remap_log("ERROR: 0:3: syntax error"),
+            "ERROR: main.frag:3: syntax error"
+        );
+    }
+
+    #[test]
+    fn remap_nvidia_log() {
+        let source = included_source("#version 330");
+        assert_eq!(
+            source.remap_log("0(11) : error C1008: undefined variable"),
+            "noise.glsl:1 : error C1008: undefined variable"
+        );
+    }
+
+    #[test]
+    fn remap_leaves_other_lines() {
+        let source = included_source("#version 330");
+        let log = "compilation failed\nwarning: lowercase 0:3";
+        assert_eq!(source.remap_log(log), log);
+    }
+}
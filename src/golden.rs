@@ -0,0 +1,456 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use glium::Texture2d;
+
+use crate::{
+    graph::{
+        Clock,
+        NodeId,
+        ShaderGraph,
+    },
+    png,
+    Error,
+};
+
+type Pixels = Vec<Vec<(u8, u8, u8, u8)>>;
+
+/// Renders a graph with a fixed clock, and compares some of
+/// its frames against golden images stored on disk, to
+/// check that a change to a graph or shader keeps its
+/// output the same. Golden images are stored as
+/// `<golden>/<output>/frame-NNNN.png`, by the frame number
+/// counting from 0.
+#[derive(Debug, Clone)]
+pub struct GoldenTest {
+    /// The frames to compare.
+    pub frames:    Vec<u64>,
+    /// The frame rate of the clock the graph is rendered
+    /// with.
+    pub fps:       f64,
+    /// The largest difference allowed in any channel of any
+    /// pixel.
+    pub tolerance: u8,
+    /// Where golden images are stored.
+    pub golden:    PathBuf,
+    /// If set, the actual frame and a diff image are
+    /// written here for each frame that does not match, in
+    /// the same layout as `golden`.
+    pub diffs:     Option<PathBuf>,
+}
+
+/// Why a frame did not match its golden image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// There is no golden image for the frame.
+    Missing,
+    /// The golden image is a different size.
+    Size {
+        expected: (u32, u32),
+        found:    (u32, u32),
+    },
+    /// Some pixels differ by more than the tolerance.
+    Pixels {
+        count:          usize,
+        max_difference: u8,
+    },
+    /// The golden image could not be read, e.g. because it
+    /// is corrupt.
+    Unreadable(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Missing => write!(f, "no golden image"),
+            Failure::Size { expected, found } => write!(
+                f,
+                "expected a {}x{} image, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            Failure::Pixels {
+                count,
+                max_difference,
+            } => write!(
+                f,
+                "{} pixels differ, by up to {}",
+                count, max_difference
+            ),
+            Failure::Unreadable(error) => {
+                write!(f, "could not read golden image: {}", error)
+            },
+        }
+    }
+}
+
+/// The result of comparing a single frame of an output.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub output:  String,
+    pub frame:   u64,
+    pub golden:  PathBuf,
+    /// `None` if the frame matched.
+    pub failure: Option<Failure>,
+}
+
+impl GoldenTest {
+    /// Where the golden image for a frame of an output is
+    /// stored.
+    pub fn golden_path(&self, output: &str, frame: u64) -> PathBuf {
+        self.golden
+            .join(output)
+            .join(format!("frame-{:0>4}.png", frame))
+    }
+
+    /// Renders the graph and compares each of its outputs
+    /// at each of `frames` against the golden images. The
+    /// same `inputs` are passed to every frame.
+    pub fn run(
+        &self,
+        graph: &mut ShaderGraph,
+        inputs: &BTreeMap<NodeId, &Texture2d>,
+    ) -> Result<Vec<Comparison>, Error> {
+        let mut comparisons = vec![];
+        self.render(graph, inputs, |output, frame, texture| {
+            let golden = self.golden_path(output, frame);
+            let actual: Pixels = texture.read();
+            let failure = compare(&actual, &golden, self.tolerance);
+
+            if let (Some(failure), Some(diffs)) = (&failure, &self.diffs) {
+                self.write_diff(
+                    diffs, output, frame, &actual, &golden, failure,
+                )?;
+            }
+
+            comparisons.push(Comparison {
+                output: output.to_string(),
+                frame,
+                golden,
+                failure,
+            });
+            Ok(())
+        })?;
+        Ok(comparisons)
+    }
+
+    /// Renders the graph and stores each of its outputs at
+    /// each of `frames` as the new golden images. Returns
+    /// the paths written.
+    pub fn bless(
+        &self,
+        graph: &mut ShaderGraph,
+        inputs: &BTreeMap<NodeId, &Texture2d>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut written = vec![];
+        self.render(graph, inputs, |output, frame, texture| {
+            let path = self.golden_path(output, frame);
+            create_parent(&path)?;
+            let pixels: Pixels = texture.read();
+            png::write_pixels(&pixels, &path)?;
+            written.push(path);
+            Ok(())
+        })?;
+        Ok(written)
+    }
+
+    /// Renders up to the last of `frames` from the start,
+    /// so recurrent shaders reach the same state each time,
+    /// calling `each` with every output at each of
+    /// `frames`.
+    fn render<F>(
+        &self,
+        graph: &mut ShaderGraph,
+        inputs: &BTreeMap<NodeId, &Texture2d>,
+        mut each: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&str, u64, &Texture2d) -> Result<(), Error>,
+    {
+        let outputs = graph.get_labeled_outputs();
+        let last = self.frames.iter().copied().max().unwrap_or(0);
+        graph.set_clock(Clock::fixed(self.fps));
+        graph.seek(0);
+
+        for frame in 0..=last {
+            let output_map = graph.forward(inputs.clone());
+            if !self.frames.contains(&frame) {
+                continue;
+            }
+            for (id, name) in outputs.iter() {
+                each(name, frame, output_map[id])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_diff(
+        &self,
+        diffs: &Path,
+        output: &str,
+        frame: u64,
+        actual: &[Vec<(u8, u8, u8, u8)>],
+        golden: &Path,
+        failure: &Failure,
+    ) -> Result<(), Error> {
+        let dir = diffs.join(output);
+        fs::create_dir_all(&dir).map_err(|error| Error::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        png::write_pixels(
+            actual,
+            &dir.join(format!("frame-{:0>4}.png", frame)),
+        )?;
+
+        // a diff only makes sense if the images line up
+        if let Failure::Pixels { .. } = failure {
+            let expected = read_golden(golden)?;
+            let diff = diff_image(actual, &expected, self.tolerance);
+            let path = dir.join(format!("frame-{:0>4}-diff.png", frame));
+            png::write_pixels(&diff, &path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn create_parent(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).map_err(|error| Error::Io {
+            path: dir.to_path_buf(),
+            error,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Reads a golden image into rows of pixels, bottom row
+/// first, like a texture.
+fn read_golden(path: &Path) -> Result<Pixels, Error> {
+    let image = png::read_png(path)?;
+    let width = image.width as usize;
+    Ok(image
+        .data
+        .chunks(4)
+        .map(|p| (p[0], p[1], p[2], p[3]))
+        .collect::<Vec<_>>()
+        .chunks(width.max(1))
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+fn size(pixels: &[Vec<(u8, u8, u8, u8)>]) -> (u32, u32) {
+    let width = pixels.first().map(|row| row.len()).unwrap_or(0);
+    (width as u32, pixels.len() as u32)
+}
+
+fn difference(a: (u8, u8, u8, u8), b: (u8, u8, u8, u8)) -> u8 {
+    [
+        a.0.abs_diff(b.0),
+        a.1.abs_diff(b.1),
+        a.2.abs_diff(b.2),
+        a.3.abs_diff(b.3),
+    ]
+    .iter()
+    .copied()
+    .max()
+    .unwrap_or(0)
+}
+
+fn compare(actual: &Pixels, golden: &Path, tolerance: u8) -> Option<Failure> {
+    if !golden.exists() {
+        return Some(Failure::Missing);
+    }
+
+    match read_golden(golden) {
+        Ok(expected) => compare_pixels(actual, &expected, tolerance),
+        Err(error) => Some(Failure::Unreadable(error.to_string())),
+    }
+}
+
+fn compare_pixels(
+    actual: &[Vec<(u8, u8, u8, u8)>],
+    expected: &[Vec<(u8, u8, u8, u8)>],
+    tolerance: u8,
+) -> Option<Failure> {
+    if size(actual) != size(expected) {
+        return Some(Failure::Size {
+            expected: size(expected),
+            found:    size(actual),
+        });
+    }
+
+    let differences: Vec<u8> = actual
+        .iter()
+        .flatten()
+        .zip(expected.iter().flatten())
+        .map(|(a, e)| difference(*a, *e))
+        .filter(|d| *d > tolerance)
+        .collect();
+
+    differences
+        .iter()
+        .max()
+        .map(|max_difference| Failure::Pixels {
+            count:          differences.len(),
+            max_difference: *max_difference,
+        })
+}
+
+/// Shows pixels that differ by more than the tolerance in
+/// red, over a faded copy of the golden image.
+fn diff_image(
+    actual: &[Vec<(u8, u8, u8, u8)>],
+    expected: &[Vec<(u8, u8, u8, u8)>],
+    tolerance: u8,
+) -> Pixels {
+    actual
+        .iter()
+        .zip(expected.iter())
+        .map(|(actual_row, expected_row)| {
+            actual_row
+                .iter()
+                .zip(expected_row.iter())
+                .map(|(a, e)| {
+                    if difference(*a, *e) > tolerance {
+                        (255, 0, 0, 255)
+                    } else {
+                        let gray =
+                            ((e.0 as u16 + e.1 as u16 + e.2 as u16) / 12) as u8;
+                        (gray, gray, gray, 255)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: (u8, u8, u8, u8) = (255, 0, 0, 255);
+    const GRAY: (u8, u8, u8, u8) = (100, 100, 100, 255);
+
+    fn image(width: usize, height: usize, pixel: (u8, u8, u8, u8)) -> Pixels {
+        vec![vec![pixel; width]; height]
+    }
+
+    /// A file in a fresh temporary directory for `test`.
+    fn temp_file(test: &str, name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "shadergarden-golden-{}-{}",
+            test,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn difference_is_the_largest_channel() {
+        assert_eq!(difference((10, 20, 30, 40), (12, 15, 30, 40)), 5);
+        assert_eq!(difference(GRAY, GRAY), 0);
+    }
+
+    #[test]
+    fn identical_images_match() {
+        assert_eq!(
+            compare_pixels(&image(3, 2, GRAY), &image(3, 2, GRAY), 0),
+            None
+        );
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let actual = image(2, 2, (102, 98, 100, 255));
+        assert_eq!(compare_pixels(&actual, &image(2, 2, GRAY), 2), None);
+        assert_eq!(
+            compare_pixels(&actual, &image(2, 2, GRAY), 1),
+            Some(Failure::Pixels {
+                count:          4,
+                max_difference: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn only_differing_pixels_are_counted() {
+        let mut actual = image(2, 2, GRAY);
+        actual[1][0] = (100, 100, 160, 255);
+        assert_eq!(
+            compare_pixels(&actual, &image(2, 2, GRAY), 10),
+            Some(Failure::Pixels {
+                count:          1,
+                max_difference: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn different_sizes_do_not_match() {
+        assert_eq!(
+            compare_pixels(&image(3, 2, GRAY), &image(2, 3, GRAY), 255),
+            Some(Failure::Size {
+                expected: (2, 3),
+                found:    (3, 2),
+            })
+        );
+    }
+
+    #[test]
+    fn diff_marks_differing_pixels() {
+        let mut actual = image(2, 1, GRAY);
+        actual[0][1] = RED;
+        let diff = diff_image(&actual, &image(2, 1, GRAY), 0);
+        assert_eq!(diff, vec![vec![(25, 25, 25, 255), RED]]);
+    }
+
+    #[test]
+    fn missing_golden() {
+        let path = temp_file("missing", "frame-0000.png");
+        assert_eq!(
+            compare(&image(1, 1, GRAY), &path, 0),
+            Some(Failure::Missing)
+        );
+    }
+
+    #[test]
+    fn corrupt_golden_is_a_failure() {
+        let path = temp_file("corrupt", "frame-0000.png");
+        fs::write(&path, b"not a png").unwrap();
+        assert!(matches!(
+            compare(&image(1, 1, GRAY), &path, 0),
+            Some(Failure::Unreadable(_))
+        ));
+    }
+
+    #[test]
+    fn written_golden_reads_back() {
+        let mut pixels = image(3, 2, GRAY);
+        pixels[0][2] = RED;
+        let path = temp_file("round-trip", "frame-0000.png");
+        png::write_pixels(&pixels, &path).unwrap();
+        assert_eq!(read_golden(&path).unwrap(), pixels);
+        assert_eq!(compare(&pixels, &path, 0), None);
+    }
+
+    #[test]
+    fn unwritable_golden_is_an_error() {
+        let file = temp_file("unwritable", "file");
+        fs::write(&file, b"").unwrap();
+        let pixels = image(1, 1, GRAY);
+        assert!(matches!(
+            png::write_pixels(&pixels, &file.join("frame-0000.png")),
+            Err(Error::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::{
+    Instant,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+/// Where a graph gets the time passed to shaders from.
+/// Use `Fixed` or `Manual` for renders that must come out
+/// the same each time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clock {
+    /// Wall-clock time, in seconds since `started`.
+    RealTime { started: Instant },
+    /// Advances by exactly `1 / fps` seconds each frame,
+    /// however long frames take to render.
+    Fixed { fps: f64 },
+    /// Only changes when set with `ShaderGraph::set_time`.
+    Manual { time: f64 },
+}
+
+impl Clock {
+    /// A clock starting now.
+    pub fn real_time() -> Clock {
+        Clock::RealTime {
+            started: Instant::now(),
+        }
+    }
+
+    /// A clock that steps at a fixed number of frames per
+    /// second.
+    pub fn fixed(fps: f64) -> Clock { Clock::Fixed { fps } }
+
+    /// A clock stopped at time 0.
+    pub fn manual() -> Clock { Clock::Manual { time: 0.0 } }
+
+    /// The time, in seconds, at a given frame.
+    pub fn time(&self, frame: u64) -> f64 {
+        match self {
+            Clock::RealTime { started } => started.elapsed().as_secs_f64(),
+            Clock::Fixed { fps } => frame as f64 / fps,
+            Clock::Manual { time } => *time,
+        }
+    }
+
+    /// The date at a given time, like Shadertoy's `iDate`.
+    /// Real-time clocks use today's date in UTC; other
+    /// clocks count `time` from the unix epoch, so that
+    /// renders are reproducible.
+    pub fn date(&self, time: f64) -> [f32; 4] {
+        match self {
+            Clock::RealTime { .. } => date(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+            ),
+            _ => date(time),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Clock { Clock::real_time() }
+}
+
+/// The year, month (starting at 0), day (starting at 1),
+/// and seconds since midnight, some number of seconds after
+/// the unix epoch.
+fn date(since_epoch: f64) -> [f32; 4] {
+    let days = (since_epoch / 86400.0).floor() as i64;
+    let seconds = since_epoch.rem_euclid(86400.0);
+
+    // days to a civil date, from
+    // http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    [year as f32, (month - 1) as f32, day as f32, seconds as f32]
+}
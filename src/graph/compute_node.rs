@@ -8,7 +8,11 @@ use glium::{
 
 use crate::{
     graph::{
-        node::Node,
+        node::{
+            Node,
+            NodeKind,
+        },
+        Edge,
         NodeId,
         UniformMap,
     },
@@ -34,14 +38,16 @@ impl<T: AsUniformValue> std::fmt::Debug for ComputeNode<T> {
 }
 
 impl<T: AsUniformValue> Node for ComputeNode<T> {
-    fn inputs(&self) -> Vec<NodeId> { vec![self.input] }
+    fn inputs(&self) -> Vec<Edge> { vec![self.input.into()] }
 
-    fn outputs(&self) -> (&str, UniformValue) {
+    fn outputs(&self) -> (&str, UniformValue<'_>) {
         ("compute", self.output.as_uniform_value())
     }
 
     fn texture(&self) -> Option<&Texture2d> { None }
 
+    fn kind(&self) -> NodeKind { NodeKind::Compute }
+
     fn forward(&mut self, _rect_strip: &RectStrip, uniforms: UniformMap<'_>) {
         self.output = (self.func)(uniforms);
     }
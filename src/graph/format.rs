@@ -0,0 +1,128 @@
+use std::{
+    fmt,
+    str::FromStr,
+};
+
+use glium::texture::UncompressedFloatFormat;
+
+use crate::Error;
+
+/// The format of the texture a shader node renders into.
+/// Formats are named after their channels and the bits per
+/// channel, e.g. `rg16f` is two 16-bit float channels.
+/// Formats without an `f` suffix are normalized integers,
+/// which can only store values between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TextureFormat {
+    R8,
+    RG8,
+    RGB8,
+    RGBA8,
+    R16,
+    RG16,
+    RGB16,
+    RGBA16,
+    R16F,
+    RG16F,
+    RGB16F,
+    RGBA16F,
+    R32F,
+    RG32F,
+    RGB32F,
+    RGBA32F,
+}
+
+/// Every format, with the name used to refer to it in graph
+/// files.
+const FORMATS: &[(TextureFormat, &str)] = &[
+    (TextureFormat::R8, "r8"),
+    (TextureFormat::RG8, "rg8"),
+    (TextureFormat::RGB8, "rgb8"),
+    (TextureFormat::RGBA8, "rgba8"),
+    (TextureFormat::R16, "r16"),
+    (TextureFormat::RG16, "rg16"),
+    (TextureFormat::RGB16, "rgb16"),
+    (TextureFormat::RGBA16, "rgba16"),
+    (TextureFormat::R16F, "r16f"),
+    (TextureFormat::RG16F, "rg16f"),
+    (TextureFormat::RGB16F, "rgb16f"),
+    (TextureFormat::RGBA16F, "rgba16f"),
+    (TextureFormat::R32F, "r32f"),
+    (TextureFormat::RG32F, "rg32f"),
+    (TextureFormat::RGB32F, "rgb32f"),
+    (TextureFormat::RGBA32F, "rgba32f"),
+];
+
+impl TextureFormat {
+    /// The name of this format, as used in graph files.
+    pub fn name(&self) -> &'static str {
+        // unwrap: every format is listed
+        FORMATS.iter().find(|(f, _)| f == self).unwrap().1
+    }
+
+    /// The number of bytes each pixel takes up.
+    pub fn bytes_per_pixel(&self) -> usize {
+        use TextureFormat::*;
+        let (channels, bytes) = match self {
+            R8 => (1, 1),
+            RG8 => (2, 1),
+            RGB8 => (3, 1),
+            RGBA8 => (4, 1),
+            R16 | R16F => (1, 2),
+            RG16 | RG16F => (2, 2),
+            RGB16 | RGB16F => (3, 2),
+            RGBA16 | RGBA16F => (4, 2),
+            R32F => (1, 4),
+            RG32F => (2, 4),
+            RGB32F => (3, 4),
+            RGBA32F => (4, 4),
+        };
+        channels * bytes
+    }
+
+    /// The underlying glium format.
+    pub fn to_glium(&self) -> UncompressedFloatFormat {
+        use UncompressedFloatFormat::*;
+        match self {
+            TextureFormat::R8 => U8,
+            TextureFormat::RG8 => U8U8,
+            TextureFormat::RGB8 => U8U8U8,
+            TextureFormat::RGBA8 => U8U8U8U8,
+            TextureFormat::R16 => U16,
+            TextureFormat::RG16 => U16U16,
+            TextureFormat::RGB16 => U16U16U16,
+            TextureFormat::RGBA16 => U16U16U16U16,
+            TextureFormat::R16F => F16,
+            TextureFormat::RG16F => F16F16,
+            TextureFormat::RGB16F => F16F16F16,
+            TextureFormat::RGBA16F => F16F16F16F16,
+            TextureFormat::R32F => F32,
+            TextureFormat::RG32F => F32F32,
+            TextureFormat::RGB32F => F32F32F32,
+            TextureFormat::RGBA32F => F32F32F32F32,
+        }
+    }
+}
+
+impl Default for TextureFormat {
+    /// 4 16-bit color channels.
+    fn default() -> TextureFormat { TextureFormat::RGBA16 }
+}
+
+impl FromStr for TextureFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<TextureFormat, Error> {
+        FORMATS
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(format, _)| *format)
+            .ok_or_else(|| Error::UnknownFormat(name.to_string()))
+    }
+}
+
+impl fmt::Display for TextureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fmt::Write,
+};
+
+use crate::graph::{
+    Edge,
+    NodeId,
+    NodeKind,
+    Param,
+    ShaderGraph,
+    TextureFormat,
+    TextureMemory,
+};
+
+/// A description of a node, for inspecting or visualizing a
+/// graph. Built with `ShaderGraph::iter_nodes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo {
+    pub id:        NodeId,
+    pub kind:      NodeKind,
+    /// The file the node's shader was loaded from, e.g.
+    /// `blur.frag`.
+    pub shader:    Option<String>,
+    /// Every name the node was bound to, as an input,
+    /// output, or variable.
+    pub names:     Vec<String>,
+    /// The width and height of the node's texture. Inputs
+    /// are only known once passed in, so have no size.
+    pub size:      Option<(u32, u32)>,
+    pub format:    Option<TextureFormat>,
+    pub inputs:    Vec<Edge>,
+    pub recurrent: bool,
+    pub output:    bool,
+    /// Whether the node is run by `forward`, see
+    /// `ShaderGraph::is_live`.
+    pub live:      bool,
+    /// The kind of uniform the node is passed to other
+    /// nodes as, e.g. `texture` for `u_texture_N`.
+    pub uniform:   String,
+}
+
+impl NodeInfo {
+    /// A short label for the node, e.g. `blur.frag` or
+    /// `input`.
+    pub fn label(&self) -> String {
+        match &self.shader {
+            Some(shader) => shader.to_string(),
+            None => self.kind.name().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for NodeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>4} {:<8} {}",
+            self.id.0,
+            self.kind.name(),
+            self.label()
+        )?;
+        if !self.names.is_empty() {
+            write!(f, " ({})", self.names.join(", "))?;
+        }
+        if let Some((width, height)) = self.size {
+            write!(f, " {}x{}", width, height)?;
+        }
+        if let Some(format) = self.format {
+            write!(f, " {}", format)?;
+        }
+        if self.recurrent {
+            write!(f, " recurrent")?;
+        }
+        if self.output {
+            write!(f, " output")?;
+        }
+        if !self.live {
+            write!(f, " dead")?;
+        }
+        if !self.inputs.is_empty() {
+            let inputs: Vec<String> = self
+                .inputs
+                .iter()
+                .map(|edge| match &edge.name {
+                    Some(name) => format!("{}={}", name, edge.node.0),
+                    None => edge.node.0.to_string(),
+                })
+                .collect();
+            write!(f, " <- {}", inputs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The uniform each of a node's inputs is bound to, e.g.
+/// `u_texture_1`, numbered as in `UniformMap`, or `u_base`
+/// for an input named `base`.
+fn input_uniforms(info: &NodeInfo, nodes: &[NodeInfo]) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    info.inputs
+        .iter()
+        .map(|edge| {
+            if let Some(name) = &edge.name {
+                return format!("u_{}", name);
+            }
+            let kind = nodes[edge.node.0].uniform.as_str();
+            let count = counts.entry(kind).or_insert(0);
+            *count += 1;
+            format!("u_{}_{}", kind, *count - 1)
+        })
+        .collect()
+}
+
+/// Escapes a string for use within double quotes, which
+/// works for both DOT and JSON.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                write!(quoted, "\\u{:04x}", c as u32).unwrap()
+            },
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Renders a graph in Graphviz's DOT language. Outputs are
+/// drawn with a double border, nodes that aren't run with a
+/// dotted border, and recurrent shaders with a dashed edge
+/// to themselves.
+pub fn to_dot(graph: &ShaderGraph) -> String {
+    let nodes: Vec<NodeInfo> = graph.iter_nodes().collect();
+    dot(&nodes)
+}
+
+fn dot(nodes: &[NodeInfo]) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph shadergarden {\n");
+    dot.push_str("    rankdir=LR;\n");
+    dot.push_str("    node [shape=box];\n");
+
+    for info in nodes.iter() {
+        let mut label = vec![info.label()];
+        if !info.names.is_empty() {
+            label.push(info.names.join(", "));
+        }
+        match (info.size, info.format) {
+            (Some((width, height)), Some(format)) => {
+                label.push(format!("{}x{} {}", width, height, format))
+            },
+            (Some((width, height)), None) => {
+                label.push(format!("{}x{}", width, height))
+            },
+            _ => (),
+        }
+
+        let shape = match info.kind {
+            NodeKind::Input => "invhouse",
+            NodeKind::Shader => "box",
+            NodeKind::Compute | NodeKind::Custom => "ellipse",
+        };
+        let peripheries = if info.output { 2 } else { 1 };
+        let style = if info.live { "solid" } else { "dotted" };
+        writeln!(
+            dot,
+            "    n{} [label={}, shape={}, peripheries={}, style={}];",
+            info.id.0,
+            quote(&label.join("\n")),
+            shape,
+            peripheries,
+            style,
+        )
+        .unwrap();
+    }
+
+    for info in nodes.iter() {
+        let uniforms = input_uniforms(info, nodes);
+        for (edge, uniform) in info.inputs.iter().zip(uniforms) {
+            writeln!(
+                dot,
+                "    n{} -> n{} [label={}];",
+                edge.node.0,
+                info.id.0,
+                quote(&uniform),
+            )
+            .unwrap();
+        }
+        if info.recurrent {
+            writeln!(
+                dot,
+                "    n{0} -> n{0} [label=\"u_previous\", style=dashed];",
+                info.id.0,
+            )
+            .unwrap();
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Renders a graph as JSON: a list of nodes, in the order
+/// they run, the graph's parameters, and the memory its
+/// textures take up, in bytes. Parameter components that
+/// aren't finite, which JSON can't represent, are `null`.
+pub fn to_json(graph: &ShaderGraph) -> String {
+    let nodes: Vec<NodeInfo> = graph.iter_nodes().collect();
+    json(&nodes, graph.get_params(), graph.texture_memory())
+}
+
+fn json(
+    nodes: &[NodeInfo],
+    params: &BTreeMap<String, Param>,
+    memory: TextureMemory,
+) -> String {
+    let optional =
+        |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+
+    let mut json = String::new();
+    json.push_str("{\n  \"nodes\": [");
+    for (index, info) in nodes.iter().enumerate() {
+        let names: Vec<String> =
+            info.names.iter().map(|name| quote(name)).collect();
+        let inputs: Vec<String> = info
+            .inputs
+            .iter()
+            .zip(input_uniforms(info, nodes))
+            .map(|(edge, uniform)| {
+                let sampler = edge.sampler.map(|sampler| {
+                    format!(
+                        "{{\"minify\": \"{:?}\", \"magnify\": \"{:?}\", \
+                         \"wrap\": \"{:?}\"}}",
+                        sampler.minify_filter,
+                        sampler.magnify_filter,
+                        sampler.wrap_function.0,
+                    )
+                });
+                format!(
+                    "{{\"node\": {}, \"uniform\": {}, \"sampler\": {}}}",
+                    edge.node.0,
+                    quote(&uniform),
+                    optional(sampler),
+                )
+            })
+            .collect();
+
+        if index > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "\n    {{\"id\": {}, \"kind\": {}, \"shader\": {}, \
+             \"names\": [{}], \"width\": {}, \"height\": {}, \
+             \"format\": {}, \"recurrent\": {}, \"output\": {}, \
+             \"live\": {}, \"inputs\": [{}]}}",
+            info.id.0,
+            quote(info.kind.name()),
+            optional(info.shader.as_deref().map(quote)),
+            names.join(", "),
+            optional(info.size.map(|(width, _)| width.to_string())),
+            optional(info.size.map(|(_, height)| height.to_string())),
+            optional(info.format.map(|format| quote(format.name()))),
+            info.recurrent,
+            info.output,
+            info.live,
+            inputs.join(", "),
+        )
+        .unwrap();
+    }
+    json.push_str("\n  ],\n  \"params\": {");
+
+    for (index, (name, param)) in params.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        // JSON has no infinity or NaN
+        let value: Vec<String> = param
+            .components()
+            .iter()
+            .map(|c| optional(c.is_finite().then(|| c.to_string())))
+            .collect();
+        write!(
+            json,
+            "\n    {}: {{\"type\": {}, \"value\": [{}]}}",
+            quote(name),
+            quote(param.glsl_type()),
+            value.join(", "),
+        )
+        .unwrap();
+    }
+    write!(
+        json,
+        "\n  }},\n  \"memory\": {{\"unpooled\": {}, \"allocated\": {}}}\n}}\n",
+        memory.unpooled, memory.allocated,
+    )
+    .unwrap();
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::uniforms::SamplerBehavior;
+
+    use super::*;
+
+    /// An input feeding a recurrent shader, the graph's
+    /// only output, which samples it twice, once by
+    /// name.
+    fn nodes() -> Vec<NodeInfo> {
+        let input = NodeInfo {
+            id:        NodeId(0),
+            kind:      NodeKind::Input,
+            shader:    None,
+            names:     vec!["video".to_string()],
+            size:      None,
+            format:    None,
+            inputs:    vec![],
+            recurrent: false,
+            output:    false,
+            live:      true,
+            uniform:   "texture".to_string(),
+        };
+        let shader = NodeInfo {
+            id:        NodeId(1),
+            kind:      NodeKind::Shader,
+            shader:    Some("blur.frag".to_string()),
+            names:     vec!["out".to_string()],
+            size:      Some((64, 32)),
+            format:    Some(TextureFormat::RGBA8),
+            inputs:    vec![
+                NodeId(0).into(),
+                Edge::sampled(NodeId(0), SamplerBehavior::default())
+                    .named("base"),
+            ],
+            recurrent: true,
+            output:    true,
+            live:      true,
+            uniform:   "texture".to_string(),
+        };
+        vec![input, shader]
+    }
+
+    const DOT: &str = r#"digraph shadergarden {
+    rankdir=LR;
+    node [shape=box];
+    n0 [label="input\nvideo", shape=invhouse, peripheries=1, style=solid];
+    n1 [label="blur.frag\nout\n64x32 rgba8", shape=box, peripheries=2, style=solid];
+    n0 -> n1 [label="u_texture_0"];
+    n0 -> n1 [label="u_base"];
+    n1 -> n1 [label="u_previous", style=dashed];
+}
+"#;
+
+    const JSON: &str = r#"{
+  "nodes": [
+    {"id": 0, "kind": "input", "shader": null, "names": ["video"], "width": null, "height": null, "format": null, "recurrent": false, "output": false, "live": true, "inputs": []},
+    {"id": 1, "kind": "shader", "shader": "blur.frag", "names": ["out"], "width": 64, "height": 32, "format": "rgba8", "recurrent": true, "output": true, "live": true, "inputs": [{"node": 0, "uniform": "u_texture_0", "sampler": null}, {"node": 0, "uniform": "u_base", "sampler": {"minify": "LinearMipmapLinear", "magnify": "Linear", "wrap": "Mirror"}}]}
+  ],
+  "params": {
+  },
+  "memory": {"unpooled": 16384, "allocated": 8192}
+}
+"#;
+
+    #[test]
+    fn quote_escapes() {
+        assert_eq!(quote("blur.frag"), "\"blur.frag\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote("a\\b"), "\"a\\\\b\"");
+        assert_eq!(quote("a\nb"), "\"a\\nb\"");
+        assert_eq!(quote("a\tb\u{1}"), "\"a\\u0009b\\u0001\"");
+        assert_eq!(quote("é"), "\"é\"");
+    }
+
+    #[test]
+    fn dot_output() {
+        assert_eq!(dot(&nodes()), DOT);
+    }
+
+    #[test]
+    fn json_output() {
+        let params = BTreeMap::new();
+        let memory = TextureMemory {
+            unpooled:  16384,
+            allocated: 8192,
+        };
+        assert_eq!(json(&nodes(), &params, memory), JSON);
+    }
+
+    #[test]
+    fn json_params_are_finite() {
+        let mut params = BTreeMap::new();
+        params.insert("gain".to_string(), Param::Float(0.5));
+        params.insert(
+            "bad".to_string(),
+            Param::Vec3([f32::INFINITY, f32::NAN, 1.0]),
+        );
+        let json = json(&[], &params, TextureMemory::default());
+        assert!(json.contains(
+            "\"bad\": {\"type\": \"vec3\", \"value\": [null, null, 1]}"
+        ));
+        assert!(
+            json.contains("\"gain\": {\"type\": \"float\", \"value\": [0.5]}")
+        );
+    }
+}
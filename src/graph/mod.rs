@@ -1,40 +1,109 @@
 use std::{
-    collections::BTreeMap,
+    borrow::Cow,
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
     rc::Rc,
-    time::Instant,
 };
 
 use glium::{
     backend::Context,
-    uniforms::AsUniformValue,
+    texture::{
+        ClientFormat,
+        MipmapsOption,
+        RawImage2d,
+        UncompressedFloatFormat,
+    },
+    uniforms::{
+        AsUniformValue,
+        MagnifySamplerFilter,
+        MinifySamplerFilter,
+        SamplerBehavior,
+        UniformType,
+        UniformValue,
+    },
+    Program,
+    Rect,
+    Surface,
     Texture2d,
 };
 
-use crate::util::{
-    compile_shader,
-    default_buffer,
-    RectStrip,
+use crate::{
+    glsl::ShaderSource,
+    util::{
+        compile_shader,
+        format_buffer,
+        RectStrip,
+    },
+    Error,
 };
 
+mod clock;
 mod compute_node;
+mod format;
+mod introspect;
 mod node;
+mod param;
+mod pool;
+mod profile;
 mod shader_node;
 mod uniform;
+mod validate;
 
+pub use clock::Clock;
 pub use compute_node::{
     ComputeNode,
     ComputeNodeFn,
 };
-pub use node::Node;
+pub use format::TextureFormat;
+pub use introspect::NodeInfo;
+pub use node::{
+    Edge,
+    Node,
+    NodeKind,
+};
+pub use param::Param;
+pub use pool::TextureMemory;
+use pool::{
+    texture_bytes,
+    Pool,
+};
+use profile::Profiler;
+pub use profile::{
+    NodeTiming,
+    Profile,
+};
 pub use shader_node::{
     Buffer,
     ShaderNode,
 };
 use uniform::UniformMap;
+pub use validate::UniformMismatch;
+use validate::{
+    glsl_type,
+    uniform_type,
+    TypedUniforms,
+};
 
 // TODO: remove the distinction between uniforms and
 // textures as inputs
 
+/// Uniforms the graph passes to shaders itself, which can't
+/// be used as names for parameters.
+const BUILTIN_UNIFORMS: &[&str] = &[
+    "time",
+    "delta",
+    "frame",
+    "date",
+    "mouse",
+    "keyboard",
+    "resolution",
+    "previous",
+    "texture",
+    "compute",
+];
+
 /// Handle that represents a particular node,
 /// in the context of a shader graph.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -44,9 +113,8 @@ pub struct NodeId(usize);
 /// Each shader is run sequentially, and can be the input to
 /// shaders later down the line.
 pub struct ShaderGraph {
-    context:     Rc<Context>,
-    rect_strip:  RectStrip,
-    pub created: std::time::Instant,
+    context:    Rc<Context>,
+    rect_strip: RectStrip,
 
     /// None is an input node.
     nodes: Vec<Option<Box<dyn Node>>>,
@@ -54,6 +122,54 @@ pub struct ShaderGraph {
     // TODO: use sets?
     inputs:  Vec<NodeId>,
     outputs: Vec<NodeId>,
+
+    /// Names given to inputs and outputs, so they can be
+    /// referred to independently of declaration order.
+    input_names:  BTreeMap<String, NodeId>,
+    output_names: BTreeMap<String, NodeId>,
+    /// Names for any other node, e.g. to look at
+    /// intermediate textures while debugging.
+    node_names:   BTreeMap<String, NodeId>,
+
+    /// Custom uniforms passed to every node.
+    params:         BTreeMap<String, Param>,
+    /// The value each parameter was declared with, to tell
+    /// which were set while the graph ran.
+    param_defaults: BTreeMap<String, Param>,
+
+    /// Where `u_time` comes from.
+    clock:     Clock,
+    /// Number of forward passes so far.
+    frame:     u64,
+    /// Time of the last forward pass.
+    last_time: Option<f64>,
+    /// Mouse and keyboard, if provided by the host.
+    mouse:     Option<[f32; 4]>,
+    keyboard:  Option<Texture2d>,
+
+    /// Times each node, if profiling is enabled.
+    profiler: Option<Profiler>,
+
+    /// Which nodes contribute to an output, and so are run
+    /// by `forward`. Found lazily, and cleared whenever the
+    /// graph changes.
+    live:         Option<Vec<bool>>,
+    /// Runs every node, even those that don't contribute to
+    /// an output.
+    evaluate_all: bool,
+
+    /// Compiled shaders, keyed by their source, so each is
+    /// only compiled once.
+    programs:         BTreeMap<String, Rc<Program>>,
+    /// Shaders compiled for the graph this one replaces,
+    /// moved into `programs` as they're used, so shaders
+    /// no longer used aren't kept across reloads.
+    reusable:         BTreeMap<String, Rc<Program>>,
+    /// The shaders this graph compiled, rather than reused.
+    compiled:         Vec<String>,
+    /// Declares the uniforms each shader is passed, so
+    /// shaders don't have to.
+    declare_uniforms: bool,
 }
 
 impl std::fmt::Debug for ShaderGraph {
@@ -62,6 +178,16 @@ impl std::fmt::Debug for ShaderGraph {
             .field("nodes", &self.nodes)
             .field("inputs", &self.inputs)
             .field("outputs", &self.outputs)
+            .field("input_names", &self.input_names)
+            .field("output_names", &self.output_names)
+            .field("node_names", &self.node_names)
+            .field("params", &self.params)
+            .field("param_defaults", &self.param_defaults)
+            .field("clock", &self.clock)
+            .field("frame", &self.frame)
+            .field("profile", &self.get_profile())
+            .field("evaluate_all", &self.evaluate_all)
+            .field("declare_uniforms", &self.declare_uniforms)
             .finish()
     }
 }
@@ -71,28 +197,396 @@ impl ShaderGraph {
     /// context.
     pub fn new(context: &Rc<Context>) -> ShaderGraph {
         ShaderGraph {
-            context:    context.clone(),
-            rect_strip: RectStrip::new(context),
-            nodes:      vec![],
-            inputs:     vec![],
-            outputs:    vec![],
-            created:    Instant::now(),
+            context:          context.clone(),
+            rect_strip:       RectStrip::new(context),
+            nodes:            vec![],
+            inputs:           vec![],
+            outputs:          vec![],
+            input_names:      BTreeMap::new(),
+            output_names:     BTreeMap::new(),
+            node_names:       BTreeMap::new(),
+            params:           BTreeMap::new(),
+            param_defaults:   BTreeMap::new(),
+            clock:            Clock::default(),
+            frame:            0,
+            last_time:        None,
+            mouse:            None,
+            keyboard:         None,
+            profiler:         None,
+            live:             None,
+            evaluate_all:     false,
+            programs:         BTreeMap::new(),
+            reusable:         BTreeMap::new(),
+            compiled:         vec![],
+            declare_uniforms: false,
         }
     }
 
+    /// The context the graph renders with.
+    pub fn get_context(&self) -> &Rc<Context> { &self.context }
+
     pub fn get_inputs(&self) -> &Vec<NodeId> { &self.inputs }
 
     pub fn get_outputs(&self) -> &Vec<NodeId> { &self.outputs }
 
+    /// Looks up an input by the name it was added with.
+    pub fn get_input(&self, name: &str) -> Option<NodeId> {
+        self.input_names.get(name).copied()
+    }
+
+    /// Lists the outputs in the order they were marked,
+    /// alongside the name each was marked with. Unnamed
+    /// outputs are called `output-N`, by their position.
+    pub fn get_labeled_outputs(&self) -> Vec<(NodeId, String)> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let name = self
+                    .output_names
+                    .iter()
+                    .find(|(_, named)| *named == id)
+                    .map(|(name, _)| name.to_string())
+                    .unwrap_or_else(|| format!("output-{}", index));
+                (*id, name)
+            })
+            .collect()
+    }
+
+    /// Looks up an output by the name it was marked with.
+    pub fn get_output(&self, name: &str) -> Option<NodeId> {
+        self.output_names.get(name).copied()
+    }
+
+    /// All named inputs, keyed by name.
+    pub fn get_named_inputs(&self) -> &BTreeMap<String, NodeId> {
+        &self.input_names
+    }
+
+    /// All named outputs, keyed by name.
+    pub fn get_named_outputs(&self) -> &BTreeMap<String, NodeId> {
+        &self.output_names
+    }
+
+    /// Gives a node a name, replacing any node previously
+    /// given the same name.
+    pub fn name_node(&mut self, id: NodeId, name: &str) {
+        self.node_names.insert(name.to_string(), id);
+    }
+
+    /// Looks up a node by the name it was given.
+    pub fn get_node(&self, name: &str) -> Option<NodeId> {
+        self.node_names.get(name).copied()
+    }
+
+    /// All named nodes, keyed by name.
+    pub fn get_named_nodes(&self) -> &BTreeMap<String, NodeId> {
+        &self.node_names
+    }
+
+    /// Gets the texture a node rendered to in the last
+    /// forward pass. Returns `None` for inputs, and nodes
+    /// that don't produce a texture. Nodes that don't
+    /// contribute to an output aren't run, so their texture
+    /// is left as it was; see `set_evaluate_all`.
+    pub fn get_texture(&self, id: NodeId) -> Option<&Texture2d> {
+        self.nodes[id.0].as_ref()?.texture()
+    }
+
+    /// Describes a node, e.g. its kind, size, and inputs.
+    pub fn get_node_info(&self, id: NodeId) -> NodeInfo {
+        self.node_info(id, self.is_live(id))
+    }
+
+    /// Describes every node in the graph, in the order they
+    /// are run.
+    pub fn iter_nodes(&self) -> impl Iterator<Item = NodeInfo> + '_ {
+        let live = match &self.live {
+            Some(live) => live.clone(),
+            None => self.find_live(),
+        };
+        (0..self.nodes.len())
+            .map(move |index| self.node_info(NodeId(index), live[index]))
+    }
+
+    fn node_info(&self, id: NodeId, live: bool) -> NodeInfo {
+        let mut names: Vec<String> = self
+            .input_names
+            .iter()
+            .chain(self.output_names.iter())
+            .chain(self.node_names.iter())
+            .filter(|(_, named)| **named == id)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        let output = self.outputs.contains(&id);
+
+        match &self.nodes[id.0] {
+            Some(node) => NodeInfo {
+                id,
+                kind: node.kind(),
+                shader: node.name().map(|name| name.to_string()),
+                names,
+                size: node
+                    .texture()
+                    .map(|t| (t.get_width(), t.get_height().unwrap())),
+                format: node.format(),
+                inputs: node.inputs(),
+                recurrent: node.recurrent(),
+                output,
+                live,
+                uniform: node.outputs().0.to_string(),
+            },
+            None => NodeInfo {
+                id,
+                kind: NodeKind::Input,
+                shader: None,
+                names,
+                size: None,
+                format: None,
+                inputs: vec![],
+                recurrent: false,
+                output,
+                live,
+                uniform: "texture".to_string(),
+            },
+        }
+    }
+
+    /// Renders the graph in Graphviz's DOT language, e.g.
+    /// to be drawn with `dot -Tsvg`.
+    pub fn to_dot(&self) -> String { introspect::to_dot(self) }
+
+    /// Renders the graph's nodes and parameters as JSON.
+    pub fn to_json(&self) -> String { introspect::to_json(self) }
+
+    /// Declares a custom uniform, passed to every shader as
+    /// `u_<name>`, with a default value. Declaring a
+    /// parameter again replaces it. Fails if the uniform
+    /// could be mistaken for one the graph already binds,
+    /// e.g. `u_time`, `u_texture_0`, or a named input.
+    pub fn add_param(
+        &mut self,
+        name: &str,
+        default: Param,
+    ) -> Result<(), Error> {
+        let input_names: Vec<String> = self
+            .nodes
+            .iter()
+            .flatten()
+            .flat_map(|node| node.inputs())
+            .filter_map(|edge| edge.name)
+            .collect();
+        let reserved = BUILTIN_UNIFORMS.iter().any(|kind| clashes(name, kind))
+            || self
+                .params
+                .keys()
+                .any(|param| param != name && clashes(name, param))
+            || input_names.iter().any(|input| clashes(name, input));
+        if reserved {
+            return Err(Error::ReservedParam(name.to_string()));
+        }
+        self.params.insert(name.to_string(), default);
+        self.param_defaults.insert(name.to_string(), default);
+        Ok(())
+    }
+
+    /// Sets a parameter for the following forward passes.
+    /// The parameter must have been declared, and the new
+    /// value must be of the same type.
+    pub fn set_param(&mut self, name: &str, value: Param) -> Result<(), Error> {
+        let param = self
+            .params
+            .get_mut(name)
+            .ok_or_else(|| Error::Undefined(name.to_string()))?;
+
+        if param.glsl_type() != value.glsl_type() {
+            return Err(Error::TypeMismatch {
+                expected: param.glsl_type(),
+                found:    value.glsl_type().to_string(),
+            });
+        }
+
+        *param = value;
+        Ok(())
+    }
+
+    /// Gets the current value of a parameter.
+    pub fn get_param(&self, name: &str) -> Option<Param> {
+        self.params.get(name).copied()
+    }
+
+    pub fn get_params(&self) -> &BTreeMap<String, Param> { &self.params }
+
+    /// Changes where the graph gets the time from. The
+    /// frame count and time carry on from where they were;
+    /// use `seek` to start again from a given frame.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+        self.last_time = None;
+    }
+
+    pub fn get_clock(&self) -> Clock { self.clock }
+
+    /// Sets the time of a manual clock, in seconds. Does
+    /// nothing for other clocks.
+    pub fn set_time(&mut self, time: f64) {
+        if let Clock::Manual {
+            time: ref mut current,
+        } = self.clock
+        {
+            *current = time;
+        }
+    }
+
+    /// Sets the number of the next frame to be rendered,
+    /// which with a fixed clock also sets the time. Note
+    /// that recurrent shaders keep their state, so seeking
+    /// does not reproduce the frames of a render that
+    /// started at `frame`.
+    pub fn seek(&mut self, frame: u64) {
+        self.frame = frame;
+        self.last_time = None;
+    }
+
+    /// The number of the next frame to be rendered.
+    pub fn get_frame(&self) -> u64 { self.frame }
+
+    /// Sets the mouse passed to shaders as `u_mouse`, laid
+    /// out like Shadertoy's `iMouse` but with positions
+    /// from 0 to 1. Each shader receives the mouse
+    /// scaled to its own resolution, in pixels. See
+    /// `util::Mouse`.
+    pub fn set_mouse(&mut self, mouse: [f32; 4]) { self.mouse = Some(mouse); }
+
+    /// Sets the keyboard texture passed to shaders as
+    /// `u_keyboard`, laid out like Shadertoy's: 256 keys
+    /// wide and 3 rows high. See `util::Keyboard`. The
+    /// texture is created on the first call, and written
+    /// into after that.
+    pub fn set_keyboard(
+        &mut self,
+        keyboard: &[[u8; 256]; 3],
+    ) -> Result<(), Error> {
+        let image = RawImage2d {
+            data:   Cow::Owned(keyboard.concat()),
+            width:  256,
+            height: 3,
+            format: ClientFormat::U8,
+        };
+
+        match &self.keyboard {
+            Some(texture) => texture.write(
+                Rect {
+                    left:   0,
+                    bottom: 0,
+                    width:  256,
+                    height: 3,
+                },
+                image,
+            ),
+            None => {
+                let texture = Texture2d::with_format(
+                    &self.context,
+                    image,
+                    UncompressedFloatFormat::U8,
+                    MipmapsOption::NoMipmap,
+                )
+                .map_err(|e| Error::Texture(e.to_string()))?;
+                self.keyboard = Some(texture);
+            },
+        }
+        Ok(())
+    }
+
+    /// Whether a node contributes to an output, and so is
+    /// run by `forward`.
+    pub fn is_live(&self, id: NodeId) -> bool {
+        match &self.live {
+            Some(live) => live[id.0],
+            None => self.find_live()[id.0],
+        }
+    }
+
+    /// By default, `forward` skips nodes that don't
+    /// contribute to any output. Set this to run every
+    /// node, e.g. to look at an intermediate texture, or
+    /// if a compute node has side effects. Recurrent
+    /// shaders are always run, so their state keeps
+    /// stepping even while they don't feed an output.
+    pub fn set_evaluate_all(&mut self, evaluate_all: bool) {
+        if self.evaluate_all != evaluate_all {
+            self.evaluate_all = evaluate_all;
+            self.live = None;
+        }
+    }
+
+    /// Finds the nodes that contribute to an output, or
+    /// to a recurrent shader, as those keep stepping every
+    /// frame.
+    fn find_live(&self) -> Vec<bool> {
+        if self.evaluate_all {
+            return vec![true; self.nodes.len()];
+        }
+
+        let nodes: Vec<Option<(Vec<NodeId>, bool)>> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                node.as_ref().map(|node| {
+                    let inputs = node.inputs().iter().map(|e| e.node).collect();
+                    (inputs, node.recurrent())
+                })
+            })
+            .collect();
+        live_nodes(&nodes, &self.outputs)
+    }
+
+    /// Enables or disables timing each node on the GPU.
+    /// While enabled, each forward pass adds to the profile
+    /// returned by `get_profile`. Fails if the context
+    /// doesn't support timer queries.
+    pub fn set_profiling(&mut self, enabled: bool) -> Result<(), Error> {
+        match (enabled, &self.profiler) {
+            (true, None) => {
+                let profiler = Profiler::new(&self.context)
+                    .ok_or(Error::UnsupportedProfiling)?;
+                self.profiler = Some(profiler);
+            },
+            (false, _) => self.profiler = None,
+            (true, Some(_)) => (),
+        }
+        Ok(())
+    }
+
+    /// The GPU time taken by each node, if profiling is
+    /// enabled. Timings are read a pass late, so the last
+    /// forward pass is not yet included.
+    pub fn get_profile(&self) -> Option<&Profile> {
+        self.profiler.as_ref().map(Profiler::profile)
+    }
+
+    /// Clears the profile, e.g. to only measure from a
+    /// certain frame.
+    pub fn reset_profile(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.reset();
+        }
+    }
+
     /// Adds anything that implements the `Node` trait to
     /// the graph Will panic if the `Node` does not
     /// preserve DAG structure.
     pub fn add_node(&mut self, node: Option<Box<dyn Node>>) -> NodeId {
         if let Some(ref node) = node {
-            self.assert_dag(&node.inputs())
+            let inputs: Vec<NodeId> =
+                node.inputs().iter().map(|edge| edge.node).collect();
+            self.assert_dag(&inputs)
         }
 
         self.nodes.push(node);
+        self.live = None;
         NodeId(self.nodes.len() - 1)
     }
 
@@ -112,63 +606,455 @@ impl ShaderGraph {
         id
     }
 
-    // TODO: custom uniforms!
+    /// Adds an input that can be looked up by name, and
+    /// passed to `forward_named`.
+    pub fn add_named_input(&mut self, name: &str) -> NodeId {
+        let id = self.add_input();
+        self.input_names.insert(name.to_string(), id);
+        id
+    }
+
     /// Adds a shader to a shader graph.
     /// Each shader has its own underlying `Texture2d` of a
-    /// particular size. Shader is bound to the graph's
-    /// context.
-    pub fn add_shader(
+    /// particular size and format. Shader is bound to the
+    /// graph's context. If `mipmaps` is set, mipmaps are
+    /// generated for the texture each time the shader runs,
+    /// so that later shaders can sample it with a mipmapped
+    /// filter.
+    pub fn add_shader<S: Into<ShaderSource>>(
         &mut self,
-        source: &str,
-        inputs: Vec<NodeId>,
+        source: S,
+        inputs: Vec<Edge>,
         width: u32,
         height: u32,
-    ) -> Result<NodeId, String> {
+        format: TextureFormat,
+        mipmaps: bool,
+    ) -> Result<NodeId, Error> {
+        self.check_format(format)?;
+        let buffer = self.buffer(width, height, format, mipmaps);
         self._add_shader(
-            source,
+            &source.into(),
             inputs,
-            Buffer::Single(default_buffer(&self.context, width, height)),
+            Buffer::Single(buffer),
+            format,
+            mipmaps,
         )
     }
 
     /// Add a recurrent shader to the graph.
-    pub fn add_rec_shader(
+    pub fn add_rec_shader<S: Into<ShaderSource>>(
         &mut self,
-        source: &str,
-        inputs: Vec<NodeId>,
+        source: S,
+        inputs: Vec<Edge>,
         width: u32,
         height: u32,
-    ) -> Result<NodeId, String> {
+        format: TextureFormat,
+        mipmaps: bool,
+    ) -> Result<NodeId, Error> {
         // set up the shader and its buffers
+        self.check_format(format)?;
         self._add_shader(
-            source,
+            &source.into(),
             inputs,
-            Buffer::new_double(|| default_buffer(&self.context, width, height)),
+            Buffer::new_double(|| self.buffer(width, height, format, mipmaps)),
+            format,
+            mipmaps,
         )
     }
 
+    fn buffer(
+        &self,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        mipmaps: bool,
+    ) -> Texture2d {
+        let mipmaps = if mipmaps {
+            MipmapsOption::EmptyMipmaps
+        } else {
+            MipmapsOption::NoMipmap
+        };
+        format_buffer(&self.context, width, height, format.to_glium(), mipmaps)
+    }
+
+    /// Checks that shaders can render into textures of the
+    /// given format with this graph's context.
+    fn check_format(&self, format: TextureFormat) -> Result<(), Error> {
+        let glium_format = format.to_glium();
+        if glium_format.is_supported(&*self.context)
+            && glium_format.is_color_renderable(&*self.context)
+        {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedFormat(format.to_string()))
+        }
+    }
+
+    /// Checks that inputs sampled with a mipmapped filter
+    /// come from nodes that generate mipmaps. Graph inputs
+    /// are passed in later, so can't be checked here.
+    fn check_mipmaps(&self, inputs: &[Edge]) -> Result<(), Error> {
+        for (index, edge) in inputs.iter().enumerate() {
+            let filter = match edge.sampler {
+                Some(sampler) => sampler.minify_filter,
+                None => continue,
+            };
+            let mipmapped = !matches!(
+                filter,
+                MinifySamplerFilter::Nearest | MinifySamplerFilter::Linear
+            );
+            let node = self.nodes.get(edge.node.0).and_then(Option::as_ref);
+
+            if let (true, Some(node)) = (mipmapped, node) {
+                // a node without mipmaps can have a single level
+                // anyway, e.g. if it's 1x1, so go by what it was
+                // declared with
+                if node.texture().is_some() && !node.mipmaps() {
+                    return Err(Error::MissingMipmaps { input: index });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that each named input can be bound to a
+    /// uniform of its own.
+    fn check_input_names(&self, inputs: &[Edge]) -> Result<(), Error> {
+        let mut names = BTreeSet::new();
+        for name in inputs.iter().filter_map(|edge| edge.name.as_deref()) {
+            let reason =
+                if BUILTIN_UNIFORMS.iter().any(|kind| clashes(name, kind))
+                    || self.params.keys().any(|param| clashes(name, param))
+                {
+                    "the graph already provides it"
+                } else if !names.insert(name) {
+                    "another input is bound to it"
+                } else if !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    "it is not a valid GLSL name"
+                } else if name
+                    .rsplit_once('_')
+                    .map(|(kind, _)| is_numbered(name, kind))
+                    .unwrap_or(false)
+                {
+                    "it ends in a number, like the uniforms the graph numbers"
+                } else {
+                    continue;
+                };
+            return Err(Error::InputName {
+                name: name.to_string(),
+                reason,
+            });
+        }
+
+        Ok(())
+    }
+
     fn _add_shader(
         &mut self,
-        source: &str,
-        inputs: Vec<NodeId>,
+        source: &ShaderSource,
+        inputs: Vec<Edge>,
         buffer: Buffer,
-    ) -> Result<NodeId, String> {
-        let shader = compile_shader(&self.context, source)?;
+        format: TextureFormat,
+        mipmaps: bool,
+    ) -> Result<NodeId, Error> {
+        self.check_mipmaps(&inputs)?;
+        self.check_input_names(&inputs)?;
+        let shader = if self.declare_uniforms {
+            let recurrent = matches!(buffer, Buffer::Double(..));
+            let (provided, _) = self.provided_uniforms(&inputs, recurrent);
+            let declarations: Vec<(String, &str)> = provided
+                .into_iter()
+                .filter_map(|(name, ty)| Some((name, glsl_type(&ty?)?)))
+                .collect();
+            let mut source = source.clone();
+            source.declare_uniforms(&declarations);
+            self.program(&source)?
+        } else {
+            self.program(source)?
+        };
 
         let shader_node = ShaderNode {
+            name: source.name().map(|name| name.to_string()),
             shader,
             inputs,
             buffer,
+            format,
+            mipmaps,
         };
         Ok(self.add_node(Some(Box::new(shader_node))))
     }
 
+    /// Compiles a shader, unless it was compiled before.
+    fn program(&mut self, source: &ShaderSource) -> Result<Rc<Program>, Error> {
+        let text = source.text();
+        if let Some(program) = self.programs.get(&text) {
+            return Ok(program.clone());
+        }
+        if let Some(program) = self.reusable.remove(&text) {
+            self.programs.insert(text, program.clone());
+            return Ok(program);
+        }
+
+        let program = Rc::new(compile_shader(&self.context, source)?);
+        self.programs.insert(text, program.clone());
+        let name = source.name().unwrap_or("<anonymous>").to_string();
+        if !self.compiled.contains(&name) {
+            self.compiled.push(name);
+        }
+        Ok(program)
+    }
+
+    /// Declares the uniforms each shader added from now on
+    /// is passed, at the top of its source, so shaders
+    /// don't have to. Uniforms a shader already declares
+    /// are left alone. Parameters are only declared for
+    /// shaders added after them.
+    pub fn set_declare_uniforms(&mut self, declare_uniforms: bool) {
+        self.declare_uniforms = declare_uniforms;
+    }
+
+    /// The uniforms `forward` passes a shader with the
+    /// given inputs, named as by `UniformMap`, with their
+    /// types where known. The mouse and keyboard are
+    /// included, as the host may provide them at any time.
+    /// Also returns the names each input can be read by.
+    fn provided_uniforms(
+        &self,
+        inputs: &[Edge],
+        recurrent: bool,
+    ) -> (TypedUniforms, Vec<Vec<String>>) {
+        let mut kinds: BTreeMap<String, Vec<Option<UniformType>>> =
+            BTreeMap::new();
+        let mut add = |kind: &str, uniform_type: Option<UniformType>| {
+            let types = kinds.entry(kind.to_string()).or_default();
+            types.push(uniform_type);
+            (kind.to_string(), types.len() - 1)
+        };
+
+        add("time", Some(UniformType::Float));
+        add("delta", Some(UniformType::Float));
+        add("frame", Some(UniformType::Int));
+        add("date", Some(UniformType::FloatVec4));
+        add("mouse", Some(UniformType::FloatVec4));
+        add("keyboard", Some(UniformType::Sampler2d));
+        for (name, param) in self.params.iter() {
+            add(name, uniform_type(&param.as_uniform_value()));
+        }
+        let edges: Vec<(String, usize)> = inputs
+            .iter()
+            .map(|edge| match &self.nodes[edge.node.0] {
+                Some(node) => {
+                    let (kind, value) = node.outputs();
+                    add(
+                        edge.name.as_deref().unwrap_or(kind),
+                        uniform_type(&value),
+                    )
+                },
+                None => add(
+                    edge.name.as_deref().unwrap_or("texture"),
+                    Some(UniformType::Sampler2d),
+                ),
+            })
+            .collect();
+        // passed by the shader node itself
+        add("resolution", Some(UniformType::FloatVec2));
+        if recurrent {
+            add("previous", Some(UniformType::Sampler2d));
+        }
+
+        let mut uniforms = vec![];
+        for (kind, types) in kinds.iter() {
+            if types.len() == 1 {
+                uniforms.push((format!("u_{}", kind), types[0]));
+            }
+            for (index, uniform_type) in types.iter().enumerate() {
+                uniforms.push((format!("u_{}_{}", kind, index), *uniform_type));
+            }
+        }
+        let edge_names = edges
+            .into_iter()
+            .map(|(kind, index)| {
+                let numbered = format!("u_{}_{}", kind, index);
+                if kinds[&kind].len() == 1 {
+                    vec![format!("u_{}", kind), numbered]
+                } else {
+                    vec![numbered]
+                }
+            })
+            .collect();
+
+        (uniforms, edge_names)
+    }
+
+    /// Compares the uniforms each shader reads with those
+    /// the graph passes it. A shader that declares a
+    /// uniform with the wrong type fails when run, so
+    /// building a graph from lisp checks for these; the
+    /// rest are left to the host to report.
+    pub fn check_uniforms(&self) -> Vec<UniformMismatch> {
+        let mut mismatches = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let (node, program) = match node {
+                Some(node) => match node.program() {
+                    Some(program) => (node, program),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let id = NodeId(index);
+            let shader = node.name().map(str::to_string);
+            let inputs = node.inputs();
+            let (provided, input_names) =
+                self.provided_uniforms(&inputs, node.recurrent());
+
+            let mut declared: Vec<_> = program
+                .uniforms()
+                .filter(|(name, _)| !name.starts_with("gl_"))
+                .collect();
+            declared.sort_by_key(|(name, _)| name.as_str());
+            for (name, uniform) in declared {
+                match provided.iter().find(|(provided, _)| provided == name) {
+                    None => mismatches.push(UniformMismatch::Missing {
+                        node:    id,
+                        shader:  shader.clone(),
+                        uniform: name.to_string(),
+                    }),
+                    Some((_, Some(provided))) if *provided != uniform.ty => {
+                        mismatches.push(UniformMismatch::Type {
+                            node:     id,
+                            shader:   shader.clone(),
+                            uniform:  name.to_string(),
+                            declared: uniform.ty,
+                            provided: *provided,
+                        })
+                    },
+                    Some(_) => (),
+                }
+            }
+
+            for (edge, names) in inputs.iter().zip(input_names) {
+                if !names.iter().any(|name| program.get_uniform(name).is_some())
+                {
+                    mismatches.push(UniformMismatch::Unused {
+                        node:    id,
+                        shader:  shader.clone(),
+                        uniform: names[0].clone(),
+                        input:   edge.node,
+                    });
+                }
+            }
+        }
+
+        mismatches
+    }
+
+    /// The names of the shaders compiled while building
+    /// this graph, in the order they were compiled.
+    /// Shaders reused with `reuse_programs` aren't
+    /// included.
+    pub fn get_compiled(&self) -> &Vec<String> { &self.compiled }
+
+    /// Reuses the shaders compiled for another graph, so
+    /// that shaders found in both aren't compiled again.
+    /// Call this before adding shaders when rebuilding a
+    /// graph. Does nothing if the graphs don't share a
+    /// context. Only the shaders this graph goes on to use
+    /// are passed on when it is itself replaced.
+    pub fn reuse_programs(&mut self, previous: &ShaderGraph) {
+        if Rc::ptr_eq(&self.context, &previous.context) {
+            self.reusable = previous.programs.clone();
+        }
+    }
+
+    /// Carries state over from the graph this one replaces:
+    /// parameters that were set while it ran and are still
+    /// declared with the same type, the clock and frame
+    /// count, and the textures of recurrent shaders still
+    /// of the same size and format. A recurrent shader is
+    /// the same if it's bound to the same name, or, if
+    /// unnamed, runs the same shader as the same number of
+    /// unnamed recurrent shaders before it. Returns the
+    /// number of shaders whose state was kept.
+    pub fn carry_over(&mut self, previous: &ShaderGraph) -> usize {
+        // a parameter still at its default takes the new one,
+        // which may have been changed in the graph file
+        for (name, value) in previous.params.iter() {
+            if previous.param_defaults.get(name) != Some(value) {
+                let _ = self.set_param(name, *value);
+            }
+        }
+        self.clock = previous.clock;
+        self.frame = previous.frame;
+        self.last_time = previous.last_time;
+        self.mouse = previous.mouse;
+
+        let old = previous.recurrent_identities();
+        let mut carried = 0;
+        for (identity, id) in self.recurrent_identities() {
+            // unwrap: identities are only found for nodes
+            let old_node = match old.get(&identity) {
+                Some(old_id) => previous.nodes[old_id.0].as_ref().unwrap(),
+                None => continue,
+            };
+            let new_node = self.nodes[id.0].as_ref().unwrap();
+            let old_state = old_node.state();
+            let new_state = new_node.state();
+
+            // a blit would convert between formats, so only
+            // textures of the same format are kept
+            let size = |t: &Texture2d| t.dimensions();
+            let same = old_node.format() == new_node.format()
+                && old_state.len() == new_state.len()
+                && old_state
+                    .iter()
+                    .zip(new_state.iter())
+                    .all(|(a, b)| size(a) == size(b));
+            if !same {
+                continue;
+            }
+
+            for (from, to) in old_state.iter().zip(new_state.iter()) {
+                from.as_surface()
+                    .fill(&to.as_surface(), MagnifySamplerFilter::Nearest);
+            }
+            carried += 1;
+        }
+
+        carried
+    }
+
+    /// Names each recurrent node, so it can be found again
+    /// in a rebuilt graph.
+    fn recurrent_identities(&self) -> BTreeMap<String, NodeId> {
+        let mut identities = BTreeMap::new();
+        let mut unnamed: BTreeMap<String, usize> = BTreeMap::new();
+
+        for info in self.iter_nodes().filter(|info| info.recurrent) {
+            let identity = match info.names.first() {
+                Some(name) => format!("let {}", name),
+                None => {
+                    let shader = info.label();
+                    let count = unnamed.entry(shader.clone()).or_insert(0);
+                    *count += 1;
+                    format!("{} #{}", shader, count)
+                },
+            };
+            identities.insert(identity, info.id);
+        }
+
+        identities
+    }
+
     /// Adds a compute node, which produces
     /// a set of uniforms for use in the next shader.
     pub fn add_compute<T: AsUniformValue + 'static>(
         &mut self,
         compute_node: ComputeNode<T>,
-    ) -> Result<NodeId, String> {
+    ) -> Result<NodeId, Error> {
         Ok(self.add_node(Some(Box::new(compute_node))))
     }
 
@@ -184,35 +1070,132 @@ impl ShaderGraph {
         }
 
         if !self.outputs.contains(&id) {
-            self.outputs.push(id)
+            self.outputs.push(id);
+            self.live = None;
         }
 
         Some(id)
     }
 
-    fn time(created: Instant) -> f32 {
-        ((Instant::now() - created).as_millis() as f64 / 1000.0) as f32
+    /// Marks a node as an output, like `mark_output`, and
+    /// names it so it is included in the map returned by
+    /// `forward_named`. A node may have more than one name.
+    pub fn mark_named_output(
+        &mut self,
+        id: NodeId,
+        name: &str,
+    ) -> Option<NodeId> {
+        self.mark_output(id)?;
+        self.output_names.insert(name.to_string(), id);
+        Some(id)
+    }
+
+    /// Shares textures between nodes that never need them
+    /// at the same time, to save memory. Outputs, named
+    /// nodes, and recurrent shaders keep their own
+    /// textures, so they can still be read after a forward
+    /// pass. Call this once the graph is built; nodes added
+    /// afterwards keep their own textures until this is
+    /// called again.
+    pub fn pool_textures(&mut self) -> TextureMemory {
+        // the last node to read each node's texture
+        let mut last_read: Vec<NodeId> =
+            (0..self.nodes.len()).map(NodeId).collect();
+        for (index, node) in self.nodes.iter().enumerate() {
+            for edge in node.iter().flat_map(|node| node.inputs()) {
+                let last = &mut last_read[edge.node.0];
+                *last = (*last).max(NodeId(index));
+            }
+        }
+
+        let kept: BTreeSet<NodeId> = self
+            .outputs
+            .iter()
+            .chain(self.output_names.values())
+            .chain(self.node_names.values())
+            .copied()
+            .collect();
+
+        // nodes are given textures in the order they run, so a
+        // texture is free once every node reading it has run
+        let mut pool = Pool::default();
+        for (index, last_read) in last_read.into_iter().enumerate() {
+            let id = NodeId(index);
+            pool.release_before(id);
+
+            let node = match &self.nodes[index] {
+                Some(node) if node.shareable() && !kept.contains(&id) => node,
+                _ => continue,
+            };
+            let key = match (node.texture(), node.format()) {
+                (Some(texture), Some(format)) => (
+                    texture.get_width(),
+                    texture.get_height().unwrap(),
+                    format,
+                    texture.get_mipmap_levels() > 1,
+                ),
+                _ => continue,
+            };
+
+            let (width, height, format, mipmaps) = key;
+            let texture = pool.take(key, last_read, || {
+                self.buffer(width, height, format, mipmaps)
+            });
+            // unwrap: checked above
+            self.nodes[index].as_mut().unwrap().share_texture(texture);
+        }
+
+        self.texture_memory()
+    }
+
+    /// How much memory the graph's textures take up. Nodes
+    /// that don't report their format aren't counted.
+    pub fn texture_memory(&self) -> TextureMemory {
+        let mut memory = TextureMemory::default();
+        let mut counted = BTreeSet::new();
+
+        for node in self.nodes.iter().flatten() {
+            let (texture, format) = match (node.texture(), node.format()) {
+                (Some(texture), Some(format)) => (texture, format),
+                _ => continue,
+            };
+            let copies = if node.recurrent() { 2 } else { 1 };
+            let bytes = texture_bytes(texture, format) * copies;
+
+            memory.unpooled += bytes;
+            if counted.insert(texture as *const Texture2d) {
+                memory.allocated += bytes;
+            }
+        }
+
+        memory
     }
 
     fn build_inputs<'a>(
         mut uniforms: UniformMap<'a>,
         previous: &'a [Option<Box<dyn Node>>],
-        inputs: &'a [NodeId],
+        inputs: &'a [Edge],
         input_map: &'a BTreeMap<NodeId, &'a Texture2d>,
     ) -> UniformMap<'a> {
         // TODO: decide what other default uniforms to use
         // let mut uniforms = UniformMap::new();
 
-        for input in inputs.iter() {
-            match &previous[input.0] {
-                Some(node) => {
-                    let (kind, uniform_value) = node.outputs();
-                    uniforms.add(kind, uniform_value);
-                },
-                None => {
-                    uniforms
-                        .add("texture", input_map[input].as_uniform_value());
+        for edge in inputs.iter() {
+            let (kind, uniform_value) = match &previous[edge.node.0] {
+                Some(node) => node.outputs(),
+                None => ("texture", input_map[&edge.node].as_uniform_value()),
+            };
+
+            // bind textures with the sampler for this edge
+            let uniform_value = match (uniform_value, edge.sampler) {
+                (UniformValue::Texture2d(texture, _), Some(sampler)) => {
+                    UniformValue::Texture2d(texture, Some(sampler))
                 },
+                (other, _) => other,
+            };
+            match &edge.name {
+                Some(name) => uniforms.add(name, uniform_value),
+                None => uniforms.add(kind, uniform_value),
             };
         }
 
@@ -243,12 +1226,13 @@ impl ShaderGraph {
 
     /// Does a forward pass of the entire shader graph.
     /// Takes a set of `N` inputs, and produces a set of `M`
-    /// outputs. Each shader in the DAG is run, from
-    /// front to back, previous input textures are bound
-    /// as uniforms of the form: `u_texture_0, ..,
-    /// u_texture_n`. Use the `map!` macro to quickly
-    /// build a `BTreeMap` to pass to this function. All
-    /// marked outputs will be included in the output map.
+    /// outputs. Each shader in the DAG that contributes to
+    /// an output is run, from front to back, previous
+    /// input textures are bound as uniforms of the form:
+    /// `u_texture_0, .., u_texture_n`. Use the `map!`
+    /// macro to quickly build a `BTreeMap` to pass to
+    /// this function. All marked outputs will be included
+    /// in the output map.
     pub fn forward<'a>(
         &'a mut self,
         input_map: BTreeMap<NodeId, &'a Texture2d>,
@@ -260,7 +1244,39 @@ impl ShaderGraph {
             assert!(input_map.contains_key(input));
         }
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.collect();
+        }
+
+        let time = self.clock.time(self.frame);
+        let delta = self.last_time.map(|last| time - last).unwrap_or(0.0);
+        let date = self.clock.date(time);
+        let frame = self.frame as i32;
+        self.last_time = Some(time);
+        self.frame += 1;
+        let (time, delta) = (time as f32, delta as f32);
+
+        if self.live.is_none() {
+            self.live = Some(self.find_live());
+        }
+
+        // the keyboard is read with `texelFetch`, so should not be
+        // filtered
+        let keyboard = self.keyboard.as_ref().map(|texture| {
+            let sampler = SamplerBehavior {
+                minify_filter: MinifySamplerFilter::Nearest,
+                magnify_filter: MagnifySamplerFilter::Nearest,
+                ..Default::default()
+            };
+            UniformValue::Texture2d(texture, Some(sampler))
+        });
+
         for split_index in 0..self.nodes.len() {
+            // unwrap: found above
+            if !self.live.as_ref().unwrap()[split_index] {
+                continue;
+            }
+
             // this is a DAG, so we can only ever reference
             // previous nodes from the current one
             // we split here so we can have multiple mutible borrows.
@@ -268,19 +1284,183 @@ impl ShaderGraph {
 
             if let Some(ref mut node) = current[0] {
                 let mut uniforms = UniformMap::new();
-                let time = Self::time(self.created);
                 uniforms.add("time", time.as_uniform_value());
+                uniforms.add("delta", delta.as_uniform_value());
+                uniforms.add("frame", UniformValue::SignedInt(frame));
+                uniforms.add("date", UniformValue::Vec4(date));
+
+                // the mouse is in pixels of the node's own output
+                if let Some([x, y, z, w]) = self.mouse {
+                    let (width, height) = node
+                        .texture()
+                        .map(|t| (t.get_width(), t.get_height().unwrap()))
+                        .unwrap_or((1, 1));
+                    let (width, height) = (width as f32, height as f32);
+                    let mouse = [x * width, y * height, z * width, w * height];
+                    uniforms.add("mouse", UniformValue::Vec4(mouse));
+                }
+                if let Some(keyboard) = keyboard {
+                    uniforms.add("keyboard", keyboard);
+                }
+
+                for (name, param) in self.params.iter() {
+                    uniforms.add(name, param.as_uniform_value());
+                }
 
                 let inputs = node.inputs();
                 let uniforms = Self::build_inputs(
                     uniforms, &*previous, &inputs, &input_map,
                 );
 
-                node.forward(&self.rect_strip, uniforms);
+                match &mut self.profiler {
+                    Some(profiler) => {
+                        let pixels = node
+                            .texture()
+                            .map(|t| {
+                                t.get_width() as u64
+                                    * t.get_height().unwrap() as u64
+                            })
+                            .unwrap_or(0);
+                        let query = profiler.start(NodeId(split_index), pixels);
+                        node.forward_timed(&self.rect_strip, uniforms, query);
+                        profiler.stop();
+                    },
+                    None => node.forward(&self.rect_strip, uniforms),
+                }
             }
         }
 
         // pulls and returns all the output textures
         self.pull_outputs(input_map)
     }
+
+    /// Does a forward pass of the entire shader graph, like
+    /// `forward`, but with inputs and outputs keyed by name
+    /// rather than `NodeId`. Returns an error if an input
+    /// name is not part of the graph, or if one of the
+    /// graph's inputs is not passed, e.g. because it is
+    /// unnamed. All named outputs will be included in the
+    /// output map.
+    pub fn forward_named<'a>(
+        &'a mut self,
+        named_inputs: BTreeMap<&str, &'a Texture2d>,
+    ) -> Result<BTreeMap<String, &'a Texture2d>, Error> {
+        let mut input_map = BTreeMap::new();
+        for (name, texture) in named_inputs {
+            let id = self
+                .get_input(name)
+                .ok_or_else(|| Error::Undefined(name.to_string()))?;
+            input_map.insert(id, texture);
+        }
+        for (index, id) in self.inputs.iter().enumerate() {
+            if !input_map.contains_key(id) {
+                let name = self
+                    .input_names
+                    .iter()
+                    .find(|(_, named)| *named == id)
+                    .map(|(name, _)| name.to_string())
+                    .unwrap_or_else(|| format!("input-{}", index));
+                return Err(Error::MissingInput(name));
+            }
+        }
+
+        let output_names = self.output_names.clone();
+        let output_map = self.forward(input_map);
+        Ok(output_names
+            .into_iter()
+            .map(|(name, id)| (name, output_map[&id]))
+            .collect())
+    }
+}
+
+/// Whether `u_<name>` is one of the numbered uniforms
+/// `u_<kind>_<n>` bound for a kind, e.g. `texture_0`.
+fn is_numbered(name: &str, kind: &str) -> bool {
+    name.strip_prefix(kind)
+        .and_then(|rest| rest.strip_prefix('_'))
+        .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or(false)
+}
+
+/// Whether the uniforms bound for two names could be the
+/// same, so one would silently take the other's place.
+fn clashes(a: &str, b: &str) -> bool {
+    a == b || is_numbered(a, b) || is_numbered(b, a)
+}
+
+/// Marks the nodes reachable from `outputs` or from a
+/// recurrent node, by walking back through the inputs of
+/// each node. Nodes are given as their inputs and whether
+/// they're recurrent.
+fn live_nodes(
+    nodes: &[Option<(Vec<NodeId>, bool)>],
+    outputs: &[NodeId],
+) -> Vec<bool> {
+    let mut live = vec![false; nodes.len()];
+    let recurrent = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| matches!(node, Some((_, true))))
+        .map(|(index, _)| NodeId(index));
+    let mut stack: Vec<NodeId> =
+        outputs.iter().copied().chain(recurrent).collect();
+
+    while let Some(id) = stack.pop() {
+        if live[id.0] {
+            continue;
+        }
+        live[id.0] = true;
+        if let Some((inputs, _)) = &nodes[id.0] {
+            stack.extend(inputs.iter().copied());
+        }
+    }
+
+    live
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_nodes_feeding_an_output_are_live() {
+        // 0 -> 1 -> 2 (output), 3 is dead
+        let nodes = vec![
+            Some((vec![], false)),
+            Some((vec![NodeId(0)], false)),
+            Some((vec![NodeId(1)], false)),
+            Some((vec![NodeId(0)], false)),
+        ];
+        assert_eq!(live_nodes(&nodes, &[NodeId(2)]), [true, true, true, false]);
+    }
+
+    #[test]
+    fn recurrent_nodes_keep_stepping() {
+        // 1 is a recurrent simulation fed by 0 that no output
+        // reads, 2 only reads 1 and is dead
+        let nodes = vec![
+            Some((vec![], false)),
+            Some((vec![NodeId(0)], true)),
+            Some((vec![NodeId(1)], false)),
+            Some((vec![], false)),
+        ];
+        assert_eq!(live_nodes(&nodes, &[NodeId(3)]), [true, true, false, true]);
+    }
+
+    #[test]
+    fn numbered_uniforms_clash() {
+        assert!(clashes("base", "base"));
+        assert!(clashes("texture_0", "texture"));
+        assert!(clashes("time", "time_1"));
+        assert!(clashes("gain_12", "gain"));
+        assert!(!clashes("texture_", "texture"));
+        assert!(!clashes("time_of_day", "time"));
+        assert!(!clashes("base", "overlay"));
+    }
+
+    #[test]
+    fn removed_nodes_are_not_live() {
+        let nodes = vec![None, Some((vec![], false))];
+        assert_eq!(live_nodes(&nodes, &[NodeId(1)]), [false, true]);
+    }
 }
@@ -1,25 +1,95 @@
+use std::rc::Rc;
+
 use glium::{
-    uniforms::UniformValue,
+    draw_parameters::TimeElapsedQuery,
+    uniforms::{
+        SamplerBehavior,
+        UniformValue,
+    },
+    Program,
     Texture2d,
 };
 
 use crate::{
     graph::{
         NodeId,
+        TextureFormat,
         UniformMap,
     },
     util::RectStrip,
 };
 
+/// An edge from one node into another, i.e. one of a
+/// node's inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub node:    NodeId,
+    /// How the input's texture is sampled. `None` uses the
+    /// texture's default sampler.
+    pub sampler: Option<SamplerBehavior>,
+    /// Binds the input to the uniform `u_<name>`, rather
+    /// than numbering it by position, e.g. `u_texture_0`.
+    pub name:    Option<String>,
+}
+
+impl Edge {
+    /// An edge that samples the input with `sampler`.
+    pub fn sampled(node: NodeId, sampler: SamplerBehavior) -> Edge {
+        Edge {
+            node,
+            sampler: Some(sampler),
+            name: None,
+        }
+    }
+
+    /// Binds the input to the uniform `u_<name>`.
+    pub fn named(mut self, name: &str) -> Edge {
+        self.name = Some(name.to_string());
+        self
+    }
+}
+
+impl From<NodeId> for Edge {
+    fn from(node: NodeId) -> Edge {
+        Edge {
+            node,
+            sampler: None,
+            name: None,
+        }
+    }
+}
+
+/// What a node is, as reported when inspecting a graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A texture passed in to each forward pass.
+    Input,
+    Shader,
+    Compute,
+    /// Any other implementation of `Node`.
+    Custom,
+}
+
+impl NodeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeKind::Input => "input",
+            NodeKind::Shader => "shader",
+            NodeKind::Compute => "compute",
+            NodeKind::Custom => "custom",
+        }
+    }
+}
+
 /// Represents a generalized shader in a shader graph.
 /// Implement this trait to add arbitrary nodes to the
 /// shader graph.
 pub trait Node: std::fmt::Debug {
     /// Not a fan, requires allocation.
-    fn inputs(&self) -> Vec<NodeId>;
+    fn inputs(&self) -> Vec<Edge>;
 
     /// Returns (kind, uniforms) tuple.
-    fn outputs(&self) -> (&str, UniformValue);
+    fn outputs(&self) -> (&str, UniformValue<'_>);
 
     // TODO: remove texture in favor of `outputs`?
     /// Denotes whether the node produces an output texture.
@@ -34,4 +104,54 @@ pub trait Node: std::fmt::Debug {
     // interface? Is there a performance hit?
     // Should rect strip have a reference to a Rc<Context>?
     fn forward(&mut self, rect_strip: &RectStrip, uniforms: UniformMap);
+
+    /// Like `forward`, but times the node's draw calls with
+    /// `query`, for profiling. Nodes that don't draw
+    /// anything are reported as taking no time.
+    fn forward_timed(
+        &mut self,
+        rect_strip: &RectStrip,
+        uniforms: UniformMap,
+        _query: &TimeElapsedQuery,
+    ) {
+        self.forward(rect_strip, uniforms)
+    }
+
+    // The following are only used to inspect the graph, so
+    // have defaults for nodes that don't care.
+
+    fn kind(&self) -> NodeKind { NodeKind::Custom }
+
+    /// The name of the shader the node runs, if any.
+    fn name(&self) -> Option<&str> { None }
+
+    /// The format of the node's texture, if it has one.
+    fn format(&self) -> Option<TextureFormat> { None }
+
+    /// Whether the node generates mipmaps for its texture,
+    /// so it can be sampled with a mipmapped filter.
+    fn mipmaps(&self) -> bool { false }
+
+    /// The compiled shader the node runs, if any, used to
+    /// check the uniforms it reads.
+    fn program(&self) -> Option<&Program> { None }
+
+    /// Whether the node reads its own previous output.
+    fn recurrent(&self) -> bool { false }
+
+    /// Whether the node's texture can be swapped for one
+    /// shared with nodes that run at other times, to save
+    /// memory. Only nodes that overwrite their whole
+    /// texture each pass, without reading it, can share.
+    fn shareable(&self) -> bool { false }
+
+    /// Swaps the node's texture for a shared one of the
+    /// same size and format. Only called if `shareable`.
+    fn share_texture(&mut self, _texture: Rc<Texture2d>) {}
+
+    /// The textures the node keeps from one pass to the
+    /// next, e.g. a recurrent shader's front and back
+    /// buffers, so they can be carried over when the graph
+    /// is rebuilt.
+    fn state(&self) -> Vec<&Texture2d> { vec![] }
 }
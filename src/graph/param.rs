@@ -0,0 +1,109 @@
+use glium::uniforms::{
+    AsUniformValue,
+    UniformValue,
+};
+
+/// The value of a custom uniform, which can be changed
+/// between forward passes. A parameter named `brightness`
+/// is passed to every shader as `u_brightness`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Param {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    Bool(bool),
+    /// Column-major, i.e. each inner array is a column.
+    Mat4([[f32; 4]; 4]),
+}
+
+impl Param {
+    /// A zeroed parameter of the given GLSL type, e.g.
+    /// `vec2`. Returns `None` if the type isn't supported.
+    pub fn zero(glsl_type: &str) -> Option<Param> {
+        let param = match glsl_type {
+            "float" => Param::Float(0.0),
+            "vec2" => Param::Vec2([0.0; 2]),
+            "vec3" => Param::Vec3([0.0; 3]),
+            "vec4" => Param::Vec4([0.0; 4]),
+            "int" => Param::Int(0),
+            "bool" => Param::Bool(false),
+            "mat4" => Param::Mat4([[0.0; 4]; 4]),
+            _ => return None,
+        };
+        Some(param)
+    }
+
+    /// The GLSL type of this parameter, e.g. `vec2`.
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            Param::Float(_) => "float",
+            Param::Vec2(_) => "vec2",
+            Param::Vec3(_) => "vec3",
+            Param::Vec4(_) => "vec4",
+            Param::Int(_) => "int",
+            Param::Bool(_) => "bool",
+            Param::Mat4(_) => "mat4",
+        }
+    }
+
+    /// The components of this parameter, flattened.
+    /// Booleans are 0 or 1.
+    pub fn components(&self) -> Vec<f32> {
+        match self {
+            Param::Float(x) => vec![*x],
+            Param::Vec2(v) => v.to_vec(),
+            Param::Vec3(v) => v.to_vec(),
+            Param::Vec4(v) => v.to_vec(),
+            Param::Int(i) => vec![*i as f32],
+            Param::Bool(b) => vec![if *b { 1.0 } else { 0.0 }],
+            Param::Mat4(m) => m.iter().flatten().copied().collect(),
+        }
+    }
+
+    /// Builds a parameter of the same type as this one from
+    /// a flat list of components. Ints are rounded, and any
+    /// nonzero value is a true boolean. Returns `None` if
+    /// the wrong number of components is given.
+    pub fn with_components(&self, components: &[f32]) -> Option<Param> {
+        if components.len() != self.components().len() {
+            return None;
+        }
+
+        let mut array = [0.0; 16];
+        array[..components.len()].copy_from_slice(components);
+        let [a, b, c, d, ..] = array;
+
+        let param = match self {
+            Param::Float(_) => Param::Float(a),
+            Param::Vec2(_) => Param::Vec2([a, b]),
+            Param::Vec3(_) => Param::Vec3([a, b, c]),
+            Param::Vec4(_) => Param::Vec4([a, b, c, d]),
+            Param::Int(_) => Param::Int(a.round() as i32),
+            Param::Bool(_) => Param::Bool(a != 0.0),
+            Param::Mat4(_) => {
+                let mut matrix = [[0.0; 4]; 4];
+                for (column, chunk) in matrix.iter_mut().zip(array.chunks(4)) {
+                    column.copy_from_slice(chunk);
+                }
+                Param::Mat4(matrix)
+            },
+        };
+        Some(param)
+    }
+}
+
+impl AsUniformValue for Param {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
+        match *self {
+            Param::Float(x) => UniformValue::Float(x),
+            Param::Vec2(v) => UniformValue::Vec2(v),
+            Param::Vec3(v) => UniformValue::Vec3(v),
+            Param::Vec4(v) => UniformValue::Vec4(v),
+            Param::Int(i) => UniformValue::SignedInt(i),
+            Param::Bool(b) => UniformValue::Bool(b),
+            Param::Mat4(m) => UniformValue::Mat4(m),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+use glium::Texture2d;
+
+use crate::graph::{
+    NodeId,
+    TextureFormat,
+};
+
+/// How much memory a graph's textures take up, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextureMemory {
+    /// If every node owned its own textures.
+    pub unpooled:  usize,
+    /// As allocated, counting shared textures once.
+    pub allocated: usize,
+}
+
+/// Textures of the same size and format can be shared.
+/// Textures with mipmaps take up more room, so aren't
+/// shared with those without.
+pub type PoolKey = (u32, u32, TextureFormat, bool);
+
+/// The number of bytes a texture takes up.
+pub fn texture_bytes(texture: &Texture2d, format: TextureFormat) -> usize {
+    let pixels = texture.get_width() as usize
+        * texture.get_height().unwrap() as usize
+        * format.bytes_per_pixel();
+    // each mipmap level is a quarter the size of the last
+    if texture.get_mipmap_levels() > 1 {
+        pixels * 4 / 3
+    } else {
+        pixels
+    }
+}
+
+/// Hands out textures to nodes, reusing textures once the
+/// nodes they were last given to are no longer needed.
+/// Nodes must be given textures in the order they run.
+pub struct Pool<T = Texture2d> {
+    /// Textures in use, with the last node that reads them.
+    taken: Vec<(NodeId, PoolKey, Rc<T>)>,
+    free:  BTreeMap<PoolKey, Vec<Rc<T>>>,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Pool {
+            taken: Vec::new(),
+            free:  BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    /// Frees every texture only read by nodes before `id`.
+    pub fn release_before(&mut self, id: NodeId) {
+        let (done, taken) = self
+            .taken
+            .drain(..)
+            .partition(|(last_read, _, _)| *last_read < id);
+        self.taken = taken;
+        for (_, key, texture) in done {
+            self.free.entry(key).or_default().push(texture);
+        }
+    }
+
+    /// Takes a free texture, or allocates one if there are
+    /// none, to be read up to and including `last_read`.
+    pub fn take<F>(
+        &mut self,
+        key: PoolKey,
+        last_read: NodeId,
+        allocate: F,
+    ) -> Rc<T>
+    where
+        F: FnOnce() -> T,
+    {
+        let texture = self
+            .free
+            .get_mut(&key)
+            .and_then(Vec::pop)
+            .unwrap_or_else(|| Rc::new(allocate()));
+        self.taken.push((last_read, key, texture.clone()));
+        texture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: PoolKey = (64, 64, TextureFormat::RGBA8, false);
+
+    /// Stands in for a texture, numbered in the order they
+    /// were allocated.
+    fn take(
+        pool: &mut Pool<usize>,
+        last_read: usize,
+        count: &mut usize,
+    ) -> usize {
+        let texture = pool.take(KEY, NodeId(last_read), || {
+            *count += 1;
+            *count - 1
+        });
+        *texture
+    }
+
+    #[test]
+    fn texture_is_kept_until_its_last_reader_runs() {
+        let mut pool = Pool::default();
+        let mut count = 0;
+
+        // node 0 is read by node 2
+        pool.release_before(NodeId(0));
+        assert_eq!(take(&mut pool, 2, &mut count), 0);
+        pool.release_before(NodeId(1));
+        assert_eq!(take(&mut pool, 3, &mut count), 1);
+        // node 2 reads texture 0, so can't render into it
+        pool.release_before(NodeId(2));
+        assert_eq!(take(&mut pool, 3, &mut count), 2);
+        pool.release_before(NodeId(3));
+        assert_eq!(take(&mut pool, 3, &mut count), 0);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn chain_alternates_between_two_textures() {
+        let mut pool = Pool::default();
+        let mut count = 0;
+
+        // like a raymarcher's passes, each read only by the next
+        let textures: Vec<usize> = (0..6)
+            .map(|index| {
+                pool.release_before(NodeId(index));
+                take(&mut pool, index + 1, &mut count)
+            })
+            .collect();
+        assert_eq!(textures, [0, 1, 0, 1, 0, 1]);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn textures_are_only_shared_with_the_same_key() {
+        let mut pool = Pool::default();
+        pool.take(KEY, NodeId(0), || 0);
+        pool.release_before(NodeId(1));
+
+        let mipmapped = (64, 64, TextureFormat::RGBA8, true);
+        assert_eq!(*pool.take(mipmapped, NodeId(1), || 1), 1);
+        assert_eq!(*pool.take(KEY, NodeId(1), || 2), 0);
+    }
+}
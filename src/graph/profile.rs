@@ -0,0 +1,132 @@
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+use glium::{
+    backend::Context,
+    draw_parameters::TimeElapsedQuery,
+};
+
+use crate::graph::NodeId;
+
+/// How long a node took to run on the GPU, over every
+/// profiled forward pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NodeTiming {
+    /// The number of forward passes timed.
+    pub samples:  u64,
+    pub total_ms: f64,
+    pub max_ms:   f64,
+    /// The number of pixels the node renders each pass,
+    /// i.e. the size of its texture.
+    pub pixels:   u64,
+}
+
+impl NodeTiming {
+    pub fn mean_ms(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.total_ms / self.samples as f64
+        }
+    }
+}
+
+/// Per-node GPU timings, collected by
+/// `ShaderGraph::forward` while profiling is enabled.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// The number of forward passes timed.
+    pub frames: u64,
+    pub nodes:  BTreeMap<NodeId, NodeTiming>,
+}
+
+impl Profile {
+    /// The mean GPU time of a whole forward pass.
+    pub fn mean_frame_ms(&self) -> f64 {
+        self.nodes.values().map(NodeTiming::mean_ms).sum()
+    }
+
+    /// Every timed node, slowest first.
+    pub fn slowest(&self) -> Vec<(NodeId, NodeTiming)> {
+        let mut nodes: Vec<(NodeId, NodeTiming)> =
+            self.nodes.iter().map(|(id, t)| (*id, *t)).collect();
+        nodes.sort_by(|(_, a), (_, b)| {
+            b.mean_ms().partial_cmp(&a.mean_ms()).unwrap()
+        });
+        nodes
+    }
+}
+
+/// Times each node with a GPU timer query. Reading a query
+/// stalls until the GPU has caught up, so each pass's
+/// queries are read at the start of the next.
+pub struct Profiler {
+    context: Rc<Context>,
+    profile: Profile,
+    pending: Vec<(NodeId, u64, TimeElapsedQuery)>,
+}
+
+impl Profiler {
+    /// Returns `None` if the context does not support timer
+    /// queries.
+    pub fn new(context: &Rc<Context>) -> Option<Profiler> {
+        TimeElapsedQuery::new(context).ok()?;
+        Some(Profiler {
+            context: context.clone(),
+            profile: Default::default(),
+            pending: vec![],
+        })
+    }
+
+    pub fn profile(&self) -> &Profile { &self.profile }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.profile = Default::default();
+    }
+
+    /// Creates a query to time a node for this pass.
+    pub fn start(&mut self, id: NodeId, pixels: u64) -> &TimeElapsedQuery {
+        // unwrap: checked on creation
+        let query = TimeElapsedQuery::new(&self.context).unwrap();
+        self.pending.push((id, pixels, query));
+        &self.pending.last().unwrap().2
+    }
+
+    /// Stops timing the node last started. glium only ends
+    /// a query once it's read, or another one begins,
+    /// which would also time everything up to then.
+    pub fn stop(&self) {
+        if let Some((_, _, query)) = self.pending.last() {
+            // checking is the only way to end the query without
+            // blocking
+            query.is_ready();
+        }
+    }
+
+    /// Adds the timings of the last pass to the profile.
+    pub fn collect(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        for (id, pixels, query) in self.pending.drain(..) {
+            // queries report nanoseconds, saturating if the result
+            // doesn't fit, which some drivers do for the first
+            // query made
+            let nanos = query.get();
+            if nanos == u32::MAX {
+                continue;
+            }
+            let ms = nanos as f64 / 1_000_000.0;
+            let timing = self.profile.nodes.entry(id).or_default();
+            timing.samples += 1;
+            timing.total_ms += ms;
+            timing.max_ms = timing.max_ms.max(ms);
+            timing.pixels = pixels;
+        }
+        self.profile.frames += 1;
+    }
+}
@@ -1,8 +1,12 @@
+use std::rc::Rc;
+
 use glium::{
+    draw_parameters::TimeElapsedQuery,
     uniforms::{
         AsUniformValue,
         UniformValue,
     },
+    DrawParameters,
     Program,
     Surface,
     Texture2d,
@@ -10,8 +14,12 @@ use glium::{
 
 use crate::{
     graph::{
-        node::Node,
-        NodeId,
+        node::{
+            Node,
+            NodeKind,
+        },
+        Edge,
+        TextureFormat,
         UniformMap,
     },
     util::RectStrip,
@@ -20,6 +28,9 @@ use crate::{
 pub enum Buffer {
     Single(Texture2d),
     Double(Texture2d, Texture2d),
+    /// A texture shared with other nodes that run at other
+    /// times, see `ShaderGraph::pool_textures`.
+    Shared(Rc<Texture2d>),
 }
 
 impl Buffer {
@@ -31,11 +42,12 @@ impl Buffer {
         match self {
             Buffer::Single(ref texture) => texture,
             Buffer::Double(ref front, _back) => front,
+            Buffer::Shared(ref texture) => texture,
         }
     }
     pub fn back(&self) -> Option<&Texture2d> {
         match self {
-            Buffer::Single(_) => None,
+            Buffer::Single(_) | Buffer::Shared(_) => None,
             Buffer::Double(_front, back) => Some(back),
         }
     }
@@ -54,36 +66,86 @@ impl Buffer {
 /// and the texture it owns that is updated in each forward
 /// pass.
 pub struct ShaderNode {
-    pub shader: Program,
-    pub inputs: Vec<NodeId>,
-    pub buffer: Buffer,
+    /// The file the shader was loaded from, if any.
+    pub name:    Option<String>,
+    /// Shared between nodes running the same shader.
+    pub shader:  Rc<Program>,
+    pub inputs:  Vec<Edge>,
+    pub buffer:  Buffer,
+    pub format:  TextureFormat,
+    /// Whether mipmaps are generated after each forward
+    /// pass. The buffer must have been created with room
+    /// for them.
+    pub mipmaps: bool,
 }
 
 impl std::fmt::Debug for ShaderNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ShaderGraph")
+        f.debug_struct("ShaderNode")
+            .field("name", &self.name)
             .field("inputs", &self.inputs)
+            .field("recurrent", &self.recurrent())
+            .field("format", &self.format)
+            .field("mipmaps", &self.mipmaps)
             .finish()
     }
 }
 
 impl Node for ShaderNode {
-    fn inputs(&self) -> Vec<NodeId> { self.inputs.to_owned() }
-
-    fn outputs(&self) -> (&str, UniformValue) {
-        match self.buffer {
-            Buffer::Single(ref texture) => {
-                ("texture", texture.as_uniform_value())
-            },
-            Buffer::Double(ref texture, _) => {
-                ("texture", texture.as_uniform_value())
-            },
-        }
+    fn inputs(&self) -> Vec<Edge> { self.inputs.to_owned() }
+
+    fn outputs(&self) -> (&str, UniformValue<'_>) {
+        ("texture", self.buffer.front().as_uniform_value())
     }
 
     fn texture(&self) -> Option<&Texture2d> { Some(self.buffer.front()) }
 
+    fn kind(&self) -> NodeKind { NodeKind::Shader }
+
+    fn name(&self) -> Option<&str> { self.name.as_deref() }
+
+    fn format(&self) -> Option<TextureFormat> { Some(self.format) }
+
+    fn mipmaps(&self) -> bool { self.mipmaps }
+
+    fn program(&self) -> Option<&Program> { Some(&self.shader) }
+
+    fn recurrent(&self) -> bool { matches!(self.buffer, Buffer::Double(..)) }
+
+    fn shareable(&self) -> bool { !self.recurrent() }
+
+    fn share_texture(&mut self, texture: Rc<Texture2d>) {
+        self.buffer = Buffer::Shared(texture);
+    }
+
+    fn state(&self) -> Vec<&Texture2d> {
+        match &self.buffer {
+            Buffer::Double(front, back) => vec![front, back],
+            _ => vec![],
+        }
+    }
+
     fn forward(&mut self, rect_strip: &RectStrip, uniforms: UniformMap) {
+        self.draw(rect_strip, uniforms, None)
+    }
+
+    fn forward_timed(
+        &mut self,
+        rect_strip: &RectStrip,
+        uniforms: UniformMap,
+        query: &TimeElapsedQuery,
+    ) {
+        self.draw(rect_strip, uniforms, Some(query))
+    }
+}
+
+impl ShaderNode {
+    fn draw(
+        &mut self,
+        rect_strip: &RectStrip,
+        uniforms: UniformMap,
+        query: Option<&TimeElapsedQuery>,
+    ) {
         self.buffer.swap();
 
         let front = self.buffer.front();
@@ -103,11 +165,20 @@ impl Node for ShaderNode {
             .as_surface()
             .draw(
                 &rect_strip.buffer,
-                &rect_strip.indices,
+                rect_strip.indices,
                 &self.shader,
                 &uniforms,
-                &Default::default(),
+                &DrawParameters {
+                    time_elapsed_query: query,
+                    ..Default::default()
+                },
             )
             .unwrap();
+
+        if self.mipmaps {
+            // safe: buffers are allocated with mipmaps when
+            // `mipmaps` is set
+            unsafe { front.generate_mipmaps() };
+        }
     }
 }
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use glium::uniforms::{
+    UniformType,
+    UniformValue,
+};
+
+use crate::graph::NodeId;
+
+/// A difference between the uniforms a shader reads, and
+/// those the graph passes it. Found by
+/// `ShaderGraph::check_uniforms`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformMismatch {
+    /// The shader reads a uniform the graph doesn't pass
+    /// it, e.g. `u_texture_2` when only two inputs are
+    /// wired, so the uniform is left at zero.
+    Missing {
+        node:    NodeId,
+        shader:  Option<String>,
+        uniform: String,
+    },
+    /// The shader never reads one of its inputs.
+    Unused {
+        node:    NodeId,
+        shader:  Option<String>,
+        uniform: String,
+        /// The node the input comes from.
+        input:   NodeId,
+    },
+    /// The shader declares a uniform with a different type
+    /// than the graph passes it, which fails when drawn.
+    Type {
+        node:     NodeId,
+        shader:   Option<String>,
+        uniform:  String,
+        declared: UniformType,
+        provided: UniformType,
+    },
+}
+
+impl UniformMismatch {
+    /// Whether the shader would fail to run, rather than
+    /// just behave unexpectedly.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, UniformMismatch::Type { .. })
+    }
+
+    fn node(&self) -> (NodeId, &Option<String>) {
+        match self {
+            UniformMismatch::Missing { node, shader, .. }
+            | UniformMismatch::Unused { node, shader, .. }
+            | UniformMismatch::Type { node, shader, .. } => (*node, shader),
+        }
+    }
+}
+
+impl fmt::Display for UniformMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (node, shader) = self.node();
+        match shader {
+            Some(shader) => {
+                write!(f, "Shader `{}` (node {}) ", shader, node.0)?
+            },
+            None => write!(f, "Node {} ", node.0)?,
+        }
+
+        match self {
+            UniformMismatch::Missing { uniform, .. } => write!(
+                f,
+                "reads `{}`, but the graph does not provide it",
+                uniform
+            ),
+            UniformMismatch::Unused { uniform, input, .. } => write!(
+                f,
+                "never reads `{}`, its input from node {}",
+                uniform, input.0
+            ),
+            UniformMismatch::Type {
+                uniform,
+                declared,
+                provided,
+                ..
+            } => write!(
+                f,
+                "declares `{}` as `{}`, but the graph provides a `{}`",
+                uniform,
+                type_name(declared),
+                type_name(provided)
+            ),
+        }
+    }
+}
+
+/// Uniforms by name, with their types where known.
+pub type TypedUniforms = Vec<(String, Option<UniformType>)>;
+
+/// The type of the uniform a value is bound to, for the
+/// kinds of values the graph passes to shaders.
+pub fn uniform_type(value: &UniformValue<'_>) -> Option<UniformType> {
+    let uniform_type = match value {
+        UniformValue::Float(_) => UniformType::Float,
+        UniformValue::Vec2(_) => UniformType::FloatVec2,
+        UniformValue::Vec3(_) => UniformType::FloatVec3,
+        UniformValue::Vec4(_) => UniformType::FloatVec4,
+        UniformValue::SignedInt(_) => UniformType::Int,
+        UniformValue::UnsignedInt(_) => UniformType::UnsignedInt,
+        UniformValue::Bool(_) => UniformType::Bool,
+        UniformValue::Mat4(_) => UniformType::FloatMat4,
+        UniformValue::Texture2d(..) => UniformType::Sampler2d,
+        _ => return None,
+    };
+    Some(uniform_type)
+}
+
+/// How a uniform of a type is declared in GLSL.
+pub fn glsl_type(uniform_type: &UniformType) -> Option<&'static str> {
+    let glsl_type = match uniform_type {
+        UniformType::Float => "float",
+        UniformType::FloatVec2 => "vec2",
+        UniformType::FloatVec3 => "vec3",
+        UniformType::FloatVec4 => "vec4",
+        UniformType::Int => "int",
+        UniformType::UnsignedInt => "uint",
+        UniformType::Bool => "bool",
+        UniformType::FloatMat4 => "mat4",
+        UniformType::Sampler2d => "sampler2D",
+        _ => return None,
+    };
+    Some(glsl_type)
+}
+
+fn type_name(uniform_type: &UniformType) -> String {
+    glsl_type(uniform_type)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:?}", uniform_type))
+}
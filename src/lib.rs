@@ -2,6 +2,14 @@ pub use glium;
 pub use include_dir;
 pub use notify;
 
+mod error;
+pub use error::{
+    Error,
+    Span,
+};
+
+pub mod glsl;
+pub mod golden;
 pub mod graph;
 #[cfg(feature = "ffmpeg")]
 pub mod input;
@@ -1,16 +1,24 @@
 use std::collections::BTreeMap;
 
-use lexpr::Value;
+use lexpr::Datum;
 
 use crate::{
+    glsl::{
+        preprocess,
+        ShaderSource,
+    },
     graph::{
         NodeId,
         ShaderGraph,
     },
     lisp::Val,
+    Error,
 };
 
-pub type FnDef = (Vec<String>, Vec<Value>);
+/// A parameter of a user-defined function, with the
+/// expression for its default value if it's optional.
+pub type FnParam = (String, Option<Datum>);
+pub type FnDef = (Vec<FnParam>, Vec<Datum>);
 
 #[derive(Debug)]
 pub struct Scope<T> {
@@ -24,14 +32,14 @@ impl<T> Scope<T> {
         }
     }
 
-    pub fn get(&self, name: &str) -> Result<&T, String> {
+    pub fn get(&self, name: &str) -> Result<&T, Error> {
         for item in self.items.iter().rev() {
             if let Some(item) = item.get(name) {
                 return Ok(item);
             }
         }
 
-        Err(format!("Item `{}` is not defined", name))
+        Err(Error::Undefined(name.to_string()))
     }
 
     pub fn set(&mut self, name: String, item: T) {
@@ -40,13 +48,17 @@ impl<T> Scope<T> {
 
     pub fn enter_scope(&mut self) { self.items.push(BTreeMap::new()) }
 
+    /// The number of nested scopes, starting at 1 for the
+    /// global scope.
+    pub fn depth(&self) -> usize { self.items.len() }
+
     pub fn exit_scope(&mut self) -> BTreeMap<String, T> {
         self.items.pop().unwrap()
     }
 }
 
 pub type ExternalFn =
-    Box<dyn Fn(&mut ShaderGraph, &[NodeId]) -> Result<NodeId, String>>;
+    Box<dyn Fn(&mut ShaderGraph, &[NodeId]) -> Result<NodeId, Error>>;
 pub type External = BTreeMap<String, ExternalFn>;
 
 // Note that functions are not first class.
@@ -59,6 +71,8 @@ pub struct Env {
     functions: Scope<FnDef>,
     /// Maps shader name to shader source.
     shaders:   BTreeMap<String, String>,
+    /// Maps library name to library source.
+    libraries: BTreeMap<String, String>,
     /// Maps names to rust functions that construct
     /// subgraphs.
     external:  External,
@@ -70,28 +84,39 @@ impl std::fmt::Debug for Env {
             .field("vars", &self.vars)
             .field("functions", &self.functions)
             .field("shaders", &self.shaders.keys().collect::<Vec<&String>>())
+            .field(
+                "libraries",
+                &self.libraries.keys().collect::<Vec<&String>>(),
+            )
             .field("external", &self.external.keys().collect::<Vec<&String>>())
             .finish()
     }
 }
 
 impl Env {
-    pub fn new(shaders: BTreeMap<String, String>, external: External) -> Env {
+    pub fn new(
+        shaders: BTreeMap<String, String>,
+        libraries: BTreeMap<String, String>,
+        external: External,
+    ) -> Env {
         Env {
             vars: Scope::new(),
             functions: Scope::new(),
             shaders,
+            libraries,
             external,
         }
     }
 
-    pub fn get(&self, name: &str) -> Result<&Val, String> {
-        self.vars.get(name)
-    }
+    pub fn get(&self, name: &str) -> Result<&Val, Error> { self.vars.get(name) }
 
     pub fn set(&mut self, name: String, item: Val) { self.vars.set(name, item) }
 
-    pub fn get_fn(&self, name: &str) -> Result<&FnDef, String> {
+    /// Whether we're evaluating at the top level, i.e. not
+    /// inside a function.
+    pub fn is_global(&self) -> bool { self.vars.depth() == 1 }
+
+    pub fn get_fn(&self, name: &str) -> Result<&FnDef, Error> {
         self.functions.get(name)
     }
 
@@ -109,17 +134,15 @@ impl Env {
         self.functions.exit_scope();
     }
 
-    pub fn shader(&self, name: &str) -> Result<&String, String> {
-        self.shaders.get(name).ok_or(format!(
-            "Could not load shader `{}`, it is not defined",
-            name
-        ))
+    /// Gets the source of a shader, with its includes
+    /// resolved, ready to be compiled.
+    pub fn shader(&self, name: &str) -> Result<ShaderSource, Error> {
+        preprocess(name, &self.shaders, &self.libraries)
     }
 
-    pub fn external(&self, name: &str) -> Result<&ExternalFn, String> {
-        self.external.get(name).ok_or(format!(
-            "Could not load external function `{}`, it is not defined",
-            name
-        ))
+    pub fn external(&self, name: &str) -> Result<&ExternalFn, Error> {
+        self.external
+            .get(name)
+            .ok_or_else(|| Error::UnknownExternal(name.to_string()))
     }
 }
@@ -1,44 +1,202 @@
 use std::{
     collections::BTreeMap,
+    ffi::OsStr,
     fs,
-    path::Path,
+    path::{
+        Path,
+        PathBuf,
+    },
 };
 
+use crate::Error;
+
 /// Loads a directory of shaders into a big ol' `BTreeMap`.
-pub fn load_shaders<T>(path: T) -> Result<BTreeMap<String, String>, String>
+/// Subdirectories are loaded too, and their shaders named
+/// by their path, so `effects/blur/gauss.frag` is named
+/// `effects/blur/gauss`. Hidden files and directories are
+/// skipped, and symlinks to directories aren't followed.
+pub fn load_shaders<T>(path: T) -> Result<BTreeMap<String, String>, Error>
+where
+    T: AsRef<Path>,
+{
+    load_files(path.as_ref(), "frag", &[])
+}
+
+/// Loads the libraries in a directory, i.e. `.glsl` files,
+/// named like the shaders of [`load_shaders`]. Libraries
+/// hold code shared between shaders, and can only be
+/// `#include`d.
+pub fn load_libraries<T>(path: T) -> Result<BTreeMap<String, String>, Error>
 where
     T: AsRef<Path>,
 {
+    load_files(path.as_ref(), "glsl", &[])
+}
+
+/// Loads the files with an extension in a directory, like
+/// [`load_shaders`], skipping the directories in `exclude`.
+pub(crate) fn load_files(
+    path: &Path,
+    extension: &str,
+    exclude: &[PathBuf],
+) -> Result<BTreeMap<String, String>, Error> {
+    // directories that don't exist yet can't be walked into
+    let exclude: Vec<PathBuf> = exclude
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect();
     let mut map = BTreeMap::new();
-    let files = fs::read_dir(path)
-        .map_err(|_| "Could not read shader directory".to_string())?;
+    load_dir(path, path, extension, &exclude, &mut map)?;
+    Ok(map)
+}
+
+fn load_dir(
+    root: &Path,
+    dir: &Path,
+    extension: &str,
+    exclude: &[PathBuf],
+    map: &mut BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let files = fs::read_dir(dir).map_err(|error| Error::Io {
+        path: dir.to_path_buf(),
+        error,
+    })?;
 
     for p in files {
         // some type stuff, you know the deal
-        let path = p.map_err(|_| "Got a bad file path".to_string())?.path();
+        let entry = p.map_err(|error| Error::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|error| Error::Io {
+            path: path.clone(),
+            error,
+        })?;
 
-        // only include `.frag` files
+        if is_hidden(&path) {
+            continue;
+        }
+        if file_type.is_dir() {
+            let excluded = path
+                .canonicalize()
+                .map(|path| exclude.contains(&path))
+                .unwrap_or(false);
+            if !excluded {
+                load_dir(root, &path, extension, exclude, map)?;
+            }
+            continue;
+        }
+        // a symlink to a directory could loop back on itself
         if path.is_dir() {
             continue;
         }
-        match path.extension() {
-            Some(x) if x.to_str() == Some("frag") => (),
-            _ => {
-                continue;
-            },
+        // only include files with the right extension
+        if path.extension() != Some(OsStr::new(extension)) {
+            continue;
         }
 
         // get the key and value, insertomundo!
-        let name = path
-            .file_stem()
-            .ok_or("Could not infer shader name from file name")?
-            .to_str()
-            .ok_or("Could not convert file name to UTF8 string")?
-            .to_string();
-        let contents = fs::read_to_string(path)
-            .map_err(|_| "Could not get shader contents")?;
+        // unwrap: `path` was found by walking `root`
+        let name = shader_name(path.strip_prefix(root).unwrap())?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| Error::Io { path, error })?;
         map.insert(name, contents);
     }
 
-    Ok(map)
+    Ok(())
+}
+
+/// Whether a file or directory is hidden, e.g. `.git`.
+pub(crate) fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Names a shader by its path relative to the shader
+/// directory, without the extension, separated by `/` on
+/// every platform.
+pub(crate) fn shader_name(relative: &Path) -> Result<String, Error> {
+    let invalid = || Error::InvalidPath(relative.to_path_buf());
+    let mut parts = vec![];
+    if let Some(parent) = relative.parent() {
+        for component in parent.iter() {
+            parts.push(component.to_str().ok_or_else(invalid)?);
+        }
+    }
+    parts.push(
+        relative
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(invalid)?,
+    );
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh temporary directory for `test`.
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "shadergarden-load-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(map: &BTreeMap<String, String>) -> Vec<&str> {
+        map.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn nested_shaders_are_named_by_path() {
+        let dir = temp_dir("nested");
+        fs::create_dir_all(dir.join("effects/blur")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("main.frag"), "").unwrap();
+        fs::write(dir.join("effects/blur/gauss.frag"), "").unwrap();
+        fs::write(dir.join("effects/noise.glsl"), "").unwrap();
+        fs::write(dir.join(".git/hidden.frag"), "").unwrap();
+
+        let shaders = load_shaders(&dir).unwrap();
+        assert_eq!(names(&shaders), ["effects/blur/gauss", "main"]);
+        let libraries = load_libraries(&dir).unwrap();
+        assert_eq!(names(&libraries), ["effects/noise"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let dir = temp_dir("symlink");
+        fs::create_dir_all(dir.join("effects")).unwrap();
+        fs::write(dir.join("effects/blur.frag"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("effects/loop")).unwrap();
+        std::os::unix::fs::symlink(
+            dir.join("effects/blur.frag"),
+            dir.join("linked.frag"),
+        )
+        .unwrap();
+
+        let shaders = load_shaders(&dir).unwrap();
+        assert_eq!(names(&shaders), ["effects/blur", "linked"]);
+    }
+
+    #[test]
+    fn excluded_directories_are_skipped() {
+        let dir = temp_dir("exclude");
+        fs::create_dir_all(dir.join("frames")).unwrap();
+        fs::write(dir.join("main.frag"), "").unwrap();
+        fs::write(dir.join("frames/stale.frag"), "").unwrap();
+
+        let exclude = [dir.join("frames"), dir.join("missing")];
+        let shaders = load_files(&dir, "frag", &exclude).unwrap();
+        assert_eq!(names(&shaders), ["main"]);
+    }
 }
@@ -1,25 +1,60 @@
 use std::rc::Rc;
 
-use glium::backend::Context;
-use lexpr::Value;
+use glium::{
+    backend::Context,
+    uniforms::{
+        MagnifySamplerFilter,
+        MinifySamplerFilter,
+        SamplerBehavior,
+        SamplerWrapFunction,
+    },
+};
+use lexpr::{
+    datum::{
+        ListIter,
+        Ref,
+    },
+    parse::{
+        KeywordSyntax,
+        Options,
+    },
+    Datum,
+    Parser,
+    Value,
+};
 
 use crate::{
+    glsl::ShaderSource,
     graph::{
+        Edge,
         NodeId,
+        Param,
         ShaderGraph,
+        TextureFormat,
     },
     reload::ShaderDir,
+    Error,
+    Span,
 };
 
 mod env;
 mod load;
 mod val;
 
+use env::FnParam;
 pub use env::{
     Env,
     External,
 };
-pub use load::load_shaders;
+pub(crate) use load::{
+    is_hidden,
+    load_files,
+    shader_name,
+};
+pub use load::{
+    load_libraries,
+    load_shaders,
+};
 pub use val::Val;
 
 /// Takes a source string of lisp that represents the shader
@@ -31,76 +66,198 @@ pub fn graph_from_sexp(
     context: &Rc<Context>,
     shader_dir: ShaderDir,
     external: External,
-) -> Result<ShaderGraph, String> {
-    let mut graph = ShaderGraph::new(context);
-    let mut env = Env::new(shader_dir.shaders, external);
+) -> Result<ShaderGraph, Error> {
+    build(ShaderGraph::new(context), shader_dir, external)
+}
 
-    // little hack to get a list of expressions
-    let sexp = lexpr::from_str(&format!("({})", shader_dir.lisp))
-        .map_err(|e| format!("{}", e))?;
+/// Like [`graph_from_sexp`], but builds a graph to replace
+/// `previous`, e.g. after its files changed. Shaders that
+/// haven't changed aren't recompiled, and recurrent shaders
+/// keep their state; see [`ShaderGraph::carry_over`].
+pub fn regraph_from_sexp(
+    previous: &ShaderGraph,
+    shader_dir: ShaderDir,
+    external: External,
+) -> Result<ShaderGraph, Error> {
+    let mut graph = ShaderGraph::new(previous.get_context());
+    graph.reuse_programs(previous);
+    let mut graph = build(graph, shader_dir, external)?;
+    graph.carry_over(previous);
+    Ok(graph)
+}
 
-    begin(&mut graph, &mut env, &sexp)?;
+fn build(
+    mut graph: ShaderGraph,
+    shader_dir: ShaderDir,
+    external: External,
+) -> Result<ShaderGraph, Error> {
+    let source = shader_dir.lisp;
+    let config = shader_dir.config;
+    let mut env = Env::new(shader_dir.shaders, shader_dir.libraries, external);
+
+    // errors are located by line and column, so they can be
+    // reported against the original graph file
+    let options =
+        Options::default().with_keyword_syntax(KeywordSyntax::ColonPrefix);
+    let forms = Parser::from_str_custom(&source, options)
+        .datum_iter()
+        .collect::<Result<Vec<Datum>, _>>()
+        .map_err(|e| {
+            let (line, column) = e
+                .location()
+                .map(|l| (l.line(), l.column().saturating_sub(1)))
+                .unwrap_or((1, 0));
+            let span = Span {
+                start: (line, column),
+                end:   (line, column + 1),
+            };
+            Error::Parse(format!("{}", e))
+                .at(span)
+                .with_source(config.as_deref(), &source)
+        })?;
+
+    begin(&mut graph, &mut env, &forms)
+        .map_err(|e| e.with_source(config.as_deref(), &source))?;
+
+    // the rest are worth a warning, but these would fail as
+    // soon as the graph is run
+    let fatal = graph.check_uniforms().into_iter().find(|m| m.is_fatal());
+    if let Some(mismatch) = fatal {
+        return Err(Error::Uniform(mismatch));
+    }
 
+    // long generated chains of shaders only need a couple of
+    // textures between them
+    graph.pool_textures();
     Ok(graph)
 }
 
-fn into_iter(sexp: &Value) -> Result<lexpr::cons::ListIter<'_>, String> {
-    sexp.list_iter()
-        .ok_or_else(|| "Expected a form".to_string())
+fn span(sexp: Ref<'_>) -> Span {
+    let span = sexp.span();
+    Span {
+        start: (span.start().line(), span.start().column()),
+        end:   (span.end().line(), span.end().column()),
+    }
+}
+
+fn into_iter(sexp: Ref<'_>) -> Result<ListIter<'_>, Error> {
+    sexp.list_iter().ok_or_else(|| {
+        Error::Syntax("Expected a form".to_string()).at(span(sexp))
+    })
 }
 
-fn next_item<'a>(
-    iter: &mut lexpr::cons::ListIter<'a>,
-) -> Result<&'a Value, String> {
+fn next_item<'a>(iter: &mut ListIter<'a>) -> Result<Ref<'a>, Error> {
     iter.next()
-        .ok_or_else(|| "Expected a non-empty form".to_string())
+        .ok_or_else(|| Error::Syntax("Expected a non-empty form".to_string()))
 }
 
-fn next_symbol<'a>(
-    iter: &mut lexpr::cons::ListIter<'a>,
-) -> Result<&'a str, String> {
-    return next_item(iter)?
-        .as_symbol()
-        .ok_or_else(|| "Expected a symbol".to_string());
+fn next_symbol<'a>(iter: &mut ListIter<'a>) -> Result<&'a str, Error> {
+    let item = next_item(iter)?;
+    item.value().as_symbol().ok_or_else(|| {
+        Error::Syntax("Expected a symbol".to_string()).at(span(item))
+    })
 }
 
-fn iter_finish(iter: lexpr::cons::ListIter<'_>) -> Result<(), String> {
-    if !iter.is_empty() {
-        Err("Unexpected extra args while parsing form".to_string())
+/// Gets the value following a keyword option, e.g. the
+/// `"r8"` in `:format "r8"`.
+fn option_value<'a>(
+    iter: &mut ListIter<'a>,
+    keyword: Ref<'a>,
+) -> Result<Ref<'a>, Error> {
+    iter.next().ok_or_else(|| {
+        Error::Syntax(format!("Expected a value after `{}`", keyword.value()))
+            .at(span(keyword))
+    })
+}
+
+fn iter_finish(iter: ListIter<'_>) -> Result<(), Error> {
+    if let Some(extra) = iter.peek() {
+        Err(Error::Syntax(
+            "Unexpected extra args while parsing form".to_string(),
+        )
+        .at(span(extra)))
     } else {
         Ok(())
     }
 }
 
 fn begin(
-    mut graph: &mut ShaderGraph,
+    graph: &mut ShaderGraph,
     env: &mut Env,
-    sexp: &Value,
-) -> Result<(), String> {
-    for declaration in into_iter(sexp)? {
-        declare(&mut graph, env, declaration)?;
+    forms: &[Datum],
+) -> Result<(), Error> {
+    for declaration in forms {
+        declare(graph, env, declaration.as_ref())?;
     }
 
     Ok(())
 }
 
+/// Evaluates a statement. Any error without a more specific
+/// location is reported against the whole statement.
 fn declare(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    sexp: &Value,
-) -> Result<(), String> {
+    sexp: Ref<'_>,
+) -> Result<(), Error> {
+    _declare(graph, env, sexp).map_err(|e| e.at(span(sexp)))
+}
+
+fn _declare(
+    graph: &mut ShaderGraph,
+    env: &mut Env,
+    sexp: Ref<'_>,
+) -> Result<(), Error> {
     let mut iter = into_iter(sexp)?;
     let keyword = next_symbol(&mut iter)?;
 
     match keyword {
         "input" => {
             let var = next_symbol(&mut iter)?;
-            let input = graph.add_input();
+            let input = graph.add_named_input(var);
             env.set(var.to_string(), Val::Node(input));
         },
         "output" => {
             let id = next_symbol(&mut iter)?;
-            graph.mark_output(env.get(id)?.to_node()?);
+            graph.mark_named_output(env.get(id)?.to_node()?, id);
+        },
+        "declare-uniforms" => graph.set_declare_uniforms(true),
+        "param" => {
+            let name = next_symbol(&mut iter)?;
+            let glsl_type = next_item(&mut iter)?;
+            let zero = glsl_type
+                .value()
+                .as_symbol()
+                .and_then(Param::zero)
+                .ok_or_else(|| {
+                    Error::Syntax(format!(
+                        "Expected a parameter type, e.g. `float` or `vec2`, \
+                         found `{}`",
+                        glsl_type.value()
+                    ))
+                    .at(span(glsl_type))
+                })?;
+
+            // booleans may be written as `#t` and `#f`
+            let mut components = vec![];
+            for component in iter.by_ref() {
+                let component = match expr(graph, env, component)? {
+                    Val::Bool(b) => b as u8 as f32,
+                    other => {
+                        other.to_float().map_err(|e| e.at(span(component)))?
+                            as f32
+                    },
+                };
+                components.push(component);
+            }
+
+            let default =
+                zero.with_components(&components).ok_or(Error::Arity {
+                    function: zero.glsl_type().to_string(),
+                    expected: zero.components().len(),
+                    found:    components.len(),
+                })?;
+            graph.add_param(name, default)?;
         },
         "define" => {
             // get the form defining the signature
@@ -108,105 +265,287 @@ fn declare(
             let mut signature = into_iter(list)?;
             let name = next_symbol(&mut signature)?;
 
-            // extract all the arguments
-            let mut args = vec![];
-            for arg in signature {
-                args.push(
-                    arg.as_symbol()
-                        .ok_or_else(|| {
-                            "Expected symbol in signature".to_string()
-                        })?
-                        .to_string(),
-                );
+            // extract all the parameters, optional ones are written
+            // `(name default)` and come after the required ones
+            let mut params: Vec<FnParam> = vec![];
+            for param in signature {
+                let (param_name, default) = match param.value().as_symbol() {
+                    Some(param_name) => (param_name, None),
+                    None => {
+                        let mut optional = into_iter(param).map_err(|_| {
+                            Error::Syntax(
+                                "Expected a symbol or `(name default)` in signature"
+                                    .to_string(),
+                            )
+                            .at(span(param))
+                        })?;
+                        let param_name = next_symbol(&mut optional)?;
+                        let default = Datum::from(next_item(&mut optional)?);
+                        iter_finish(optional)?;
+                        (param_name, Some(default))
+                    },
+                };
+
+                if params.iter().any(|(name, _)| name == param_name) {
+                    return Err(Error::Syntax(format!(
+                        "Parameter `{}` is declared more than once",
+                        param_name
+                    ))
+                    .at(span(param)));
+                }
+                if default.is_none() && params.iter().any(|(_, d)| d.is_some())
+                {
+                    return Err(Error::Syntax(format!(
+                        "Required parameter `{}` must come before optional ones",
+                        param_name
+                    ))
+                    .at(span(param)));
+                }
+                params.push((param_name.to_string(), default));
             }
 
-            let forms: Vec<Value> = iter.map(|f| f.to_owned()).collect();
+            let forms: Vec<Datum> = iter.map(Datum::from).collect();
             if forms.is_empty() {
-                return Err(format!(
+                return Err(Error::Syntax(format!(
                     "Definition `{}` must have at least one expression in body",
                     name
-                ));
+                )));
             }
-            env.set_fn(name.to_string(), (args, forms));
+            env.set_fn(name.to_string(), (params, forms));
             return Ok(());
         },
         "let" => {
             let var = next_symbol(&mut iter)?;
             let val = expr(graph, env, next_item(&mut iter)?)?;
+            // name top-level nodes, so they can be found later
+            if let (Val::Node(id), true) = (&val, env.is_global()) {
+                graph.name_node(*id, var);
+            }
             env.set(var.to_string(), val);
         },
         "repeat" => {
-            let times = expr(graph, env, next_item(&mut iter)?)?.to_nat()?;
-            let forms: Vec<Value> = iter.map(|f| f.to_owned()).collect();
+            let times =
+                expr_as(graph, env, next_item(&mut iter)?, Val::to_nat)?;
+            let forms: Vec<Ref> = iter.collect();
             for _ in 0..times {
                 for form in forms.iter() {
-                    declare(graph, env, form)?;
+                    declare(graph, env, *form)?;
                 }
             }
             return Ok(());
         },
         other => {
-            return Err(format!(
+            return Err(Error::Syntax(format!(
                 "Expected a statement keyword, found `{}`",
                 other
-            ))
+            )))
         },
     }
 
     iter_finish(iter)
 }
 
+/// Evaluates an expression. Any error without a more
+/// specific location is reported against the whole
+/// expression.
 fn expr(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    value: &Value,
-) -> Result<Val, String> {
-    let val = match value {
+    value: Ref<'_>,
+) -> Result<Val, Error> {
+    _expr(graph, env, value).map_err(|e| e.at(span(value)))
+}
+
+fn _expr(
+    graph: &mut ShaderGraph,
+    env: &mut Env,
+    value: Ref<'_>,
+) -> Result<Val, Error> {
+    let val = match value.value() {
         Value::Number(n) => {
             if let Some(float) = n.as_f64() {
                 Val::Number(float)
             } else {
-                return Err("unexpected number type".to_string());
+                return Err(Error::Syntax(
+                    "unexpected number type".to_string(),
+                ));
             }
         },
 
         Value::Bool(b) => Val::Bool(*b),
-        Value::String(_) => Val::String(value.as_str().unwrap().to_string()),
-        Value::Symbol(_) => env.get(value.as_symbol().unwrap())?.clone(),
+        Value::String(string) => Val::String(string.to_string()),
+        Value::Symbol(symbol) => env.get(symbol)?.clone(),
 
-        x if x.is_list() => node(graph, env, x)?,
+        x if x.is_list() => node(graph, env, value)?,
 
-        other => return Err(format!("unexpected value `{}`", other)),
+        other => {
+            return Err(Error::Syntax(format!("unexpected value `{}`", other)))
+        },
     };
 
     Ok(val)
 }
 
+/// Evaluates an expression and converts the result, so
+/// type mismatches are reported against the expression
+/// itself rather than the enclosing form.
+fn expr_as<T>(
+    graph: &mut ShaderGraph,
+    env: &mut Env,
+    value: Ref<'_>,
+    into: fn(&Val) -> Result<T, Error>,
+) -> Result<T, Error> {
+    expr(graph, env, value)
+        .and_then(|val| into(&val))
+        .map_err(|e| e.at(span(value)))
+}
+
+/// The arguments of a `shader`, `shader-rec`, or
+/// `shader-param` declaration.
+struct ShaderArgs {
+    name:    String,
+    width:   u32,
+    height:  u32,
+    inputs:  Vec<Edge>,
+    format:  TextureFormat,
+    mipmaps: bool,
+}
+
 fn shader(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    mut iter: lexpr::cons::ListIter<'_>,
-) -> Result<(String, u32, u32, Vec<NodeId>), String> {
-    let name = expr(graph, env, next_item(&mut iter)?)?.to_string()?;
-    let width = expr(graph, env, next_item(&mut iter)?)?.to_nat()?;
-    let height = expr(graph, env, next_item(&mut iter)?)?.to_nat()?;
+    mut iter: ListIter<'_>,
+) -> Result<ShaderArgs, Error> {
+    let name = expr_as(graph, env, next_item(&mut iter)?, Val::to_string)?;
+    let width = expr_as(graph, env, next_item(&mut iter)?, Val::to_nat)?;
+    let height = expr_as(graph, env, next_item(&mut iter)?, Val::to_nat)?;
     let mut inputs = vec![];
-    for remaining in iter {
-        let node_id = expr(graph, env, remaining)?.to_node()?;
-        inputs.push(node_id);
+    let mut format = TextureFormat::default();
+    let mut mipmaps = false;
+
+    // inputs, mixed with keyword options
+    while let Some(remaining) = iter.next() {
+        match remaining.value().as_keyword() {
+            Some("format") => {
+                let value = option_value(&mut iter, remaining)?;
+                format = expr_as(graph, env, value, Val::to_string)?
+                    .parse()
+                    .map_err(|e: Error| e.at(span(value)))?;
+            },
+            Some("mipmaps") => {
+                let value = option_value(&mut iter, remaining)?;
+                mipmaps = expr_as(graph, env, value, Val::to_bool)?;
+            },
+            // any other keyword names the input after it, so
+            // is taken for a misspelt option if no node follows
+            Some(name) => {
+                let value = option_value(&mut iter, remaining)?;
+                let val =
+                    expr(graph, env, value).map_err(|e| e.at(span(value)))?;
+                let edge = val.to_edge().map_err(|_| {
+                    Error::Syntax(format!(
+                        "Unknown shader option `:{}`, or an input named \
+                         `{}` that isn't a node",
+                        name, name
+                    ))
+                    .at(span(remaining))
+                })?;
+                inputs.push(edge.named(name));
+            },
+            None => {
+                let edge = expr_as(graph, env, remaining, Val::to_edge)?;
+                inputs.push(edge);
+            },
+        }
+    }
+
+    Ok(ShaderArgs {
+        name,
+        width: width as u32,
+        height: height as u32,
+        inputs,
+        format,
+        mipmaps,
+    })
+}
+
+/// Parses the options of a `sample` form. Unspecified
+/// options default to linear filtering and clamping to the
+/// edge.
+fn sampler(
+    graph: &mut ShaderGraph,
+    env: &mut Env,
+    mut iter: ListIter<'_>,
+) -> Result<SamplerBehavior, Error> {
+    let mut sampler = SamplerBehavior {
+        wrap_function: (
+            SamplerWrapFunction::Clamp,
+            SamplerWrapFunction::Clamp,
+            SamplerWrapFunction::Clamp,
+        ),
+        minify_filter: MinifySamplerFilter::Linear,
+        magnify_filter: MagnifySamplerFilter::Linear,
+        ..Default::default()
+    };
+
+    while let Some(keyword) = iter.next() {
+        let option = keyword.value().as_keyword().ok_or_else(|| {
+            Error::Syntax("Expected a sampler option, e.g. `:wrap`".to_string())
+                .at(span(keyword))
+        })?;
+        let value = option_value(&mut iter, keyword)?;
+        let choice = expr_as(graph, env, value, Val::to_string)?;
+
+        match (option, choice.as_str()) {
+            ("filter", "nearest") => {
+                sampler.minify_filter = MinifySamplerFilter::Nearest;
+                sampler.magnify_filter = MagnifySamplerFilter::Nearest;
+            },
+            ("filter", "linear") => {
+                sampler.minify_filter = MinifySamplerFilter::Linear;
+                sampler.magnify_filter = MagnifySamplerFilter::Linear;
+            },
+            ("filter", "mipmap") => {
+                sampler.minify_filter = MinifySamplerFilter::LinearMipmapLinear;
+                sampler.magnify_filter = MagnifySamplerFilter::Linear;
+            },
+            ("wrap", wrap @ ("clamp" | "repeat" | "mirror")) => {
+                let wrap = match wrap {
+                    "clamp" => SamplerWrapFunction::Clamp,
+                    "repeat" => SamplerWrapFunction::Repeat,
+                    _ => SamplerWrapFunction::Mirror,
+                };
+                sampler.wrap_function = (wrap, wrap, wrap);
+            },
+            ("filter", other) | ("wrap", other) => {
+                return Err(Error::Syntax(format!(
+                    "Unknown {} `{}`",
+                    option, other
+                ))
+                .at(span(value)));
+            },
+            (other, _) => {
+                return Err(Error::Syntax(format!(
+                    "Unknown sampler option `:{}`",
+                    other
+                ))
+                .at(span(keyword)));
+            },
+        }
     }
-    Ok((name, width as u32, height as u32, inputs))
+
+    Ok(sampler)
 }
 
 fn external(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    mut iter: lexpr::cons::ListIter<'_>,
-) -> Result<(String, Vec<NodeId>), String> {
-    let name = expr(graph, env, next_item(&mut iter)?)?.to_string()?;
+    mut iter: ListIter<'_>,
+) -> Result<(String, Vec<NodeId>), Error> {
+    let name = expr_as(graph, env, next_item(&mut iter)?, Val::to_string)?;
     let mut inputs = vec![];
     for remaining in iter {
-        let node_id = expr(graph, env, remaining)?.to_node()?;
+        let node_id = expr_as(graph, env, remaining, Val::to_node)?;
         inputs.push(node_id);
     }
     Ok((name, inputs))
@@ -215,19 +554,21 @@ fn external(
 fn node(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    sexp: &Value,
-) -> Result<Val, String> {
+    sexp: Ref<'_>,
+) -> Result<Val, Error> {
     let mut iter = into_iter(sexp)?;
     let function = next_symbol(&mut iter)?;
 
     match function {
         "shader" => {
-            let (name, width, height, inputs) = shader(graph, env, iter)?;
+            let args = shader(graph, env, iter)?;
             let node_id = graph.add_shader(
-                env.shader(&name)?,
-                inputs,
-                width as u32,
-                height as u32,
+                env.shader(&args.name)?,
+                args.inputs,
+                args.width,
+                args.height,
+                args.format,
+                args.mipmaps,
             )?;
             Ok(Val::Node(node_id))
         },
@@ -235,8 +576,8 @@ fn node(
             // get the shader we'll be running the transformations
             // against
             let decl = into_iter(next_item(&mut iter)?)?;
-            let (name, width, height, inputs) = shader(graph, env, decl)?;
-            let mut source = env.shader(&name)?.to_string();
+            let args = shader(graph, env, decl)?;
+            let mut source = env.shader(&args.name)?;
 
             // parse the substitutions to be applied
             // let mut subst = vec![];
@@ -245,81 +586,180 @@ fn node(
             }
 
             let node_id = graph.add_shader(
-                &source,
-                inputs,
-                width as u32,
-                height as u32,
+                source,
+                args.inputs,
+                args.width,
+                args.height,
+                args.format,
+                args.mipmaps,
             )?;
             Ok(Val::Node(node_id))
         },
         "shader-rec" => {
-            let (name, width, height, inputs) = shader(graph, env, iter)?;
+            let args = shader(graph, env, iter)?;
             let node_id = graph.add_rec_shader(
-                env.shader(&name)?,
-                inputs,
-                width as u32,
-                height as u32,
+                env.shader(&args.name)?,
+                args.inputs,
+                args.width,
+                args.height,
+                args.format,
+                args.mipmaps,
             )?;
             Ok(Val::Node(node_id))
         },
+        "sample" => {
+            let node_id =
+                expr_as(graph, env, next_item(&mut iter)?, Val::to_node)?;
+            let sampler = sampler(graph, env, iter)?;
+            Ok(Val::Sampled(Edge::sampled(node_id, sampler)))
+        },
         "extern" => {
             let (name, inputs) = external(graph, env, iter)?;
             let adder = env.external(&name)?;
-            let node_id = adder(graph, &inputs).map_err(|e| {
-                format!("While adding external function `{}`: {}", name, e)
-            })?;
+            let node_id =
+                adder(graph, &inputs).map_err(|e| Error::External {
+                    name:  name.to_string(),
+                    error: Box::new(e),
+                })?;
             Ok(Val::Node(node_id))
         },
         user_defined => {
-            // evaluate the arguments (pass by value)
+            // evaluate the arguments (pass by value), keeping
+            // keyword arguments apart to bind them by name
             let mut args = vec![];
-            for arg in iter {
-                args.push(expr(graph, env, arg)?);
+            let mut keyword_args = vec![];
+            while let Some(arg) = iter.next() {
+                match arg.value().as_keyword() {
+                    Some(keyword) => {
+                        let value = option_value(&mut iter, arg)?;
+                        keyword_args.push((
+                            keyword,
+                            expr(graph, env, value)?,
+                            span(arg),
+                        ));
+                    },
+                    None => args.push(expr(graph, env, arg)?),
+                }
             }
 
-            if let Some(val) = builtin(user_defined, &args) {
-                return val;
+            if keyword_args.is_empty() {
+                if let Some(val) = builtin(user_defined, &args) {
+                    return val;
+                }
             }
 
             // get the function
             let (params, body) = env.get_fn(user_defined)?.clone();
 
-            // check things match up before calling
-            if params.len() != args.len() {
-                return Err(format!(
-                    "function `{}` expected {} args, but found {}",
-                    user_defined,
-                    params.len(),
-                    args.len(),
-                ));
-            }
-
             // evaluate in new scope, declare arguments
+            // defaults are evaluated here too, with the parameters
+            // before them declared
             // recursive definitions will bap the stack, so please don't
             // write them
             env.enter_scope();
-            for (name, val) in params.iter().zip(args.iter()) {
-                env.set(name.to_string(), val.to_owned())
-            }
+            let called_from =
+                |e: Error| e.called_from(user_defined, span(sexp));
+            let bound = bind_args(
+                user_defined,
+                &params,
+                args,
+                keyword_args,
+                |earlier, default| {
+                    for (name, val) in earlier {
+                        env.set(name.to_string(), val.clone());
+                    }
+                    expr(graph, env, default.as_ref()).map_err(called_from)
+                },
+            );
 
             // unwrap is ok because length is checked when adding
             // definiton
             let last = body.last().unwrap();
             let declarations = &body[..body.len() - 1];
-            for declaration in declarations {
-                declare(graph, env, declaration)?;
-            }
-
-            // TODO: multiple returns how?
-            // last value must be an expression, return it
-            let ret = expr(graph, env, last)?.to_node()?;
+            let ret = bound.and_then(|bound| {
+                for (name, val) in bound {
+                    env.set(name, val);
+                }
+                declarations
+                    .iter()
+                    .try_for_each(|d| declare(graph, env, d.as_ref()))
+                    // TODO: multiple returns how?
+                    // last value must be an expression, return it
+                    .and_then(|()| {
+                        expr_as(graph, env, last.as_ref(), Val::to_node)
+                    })
+                    .map_err(called_from)
+            });
             env.exit_scope();
-            Ok(Val::Node(ret))
+            Ok(Val::Node(ret?))
         },
     }
 }
 
-fn builtin(name: &str, args: &[Val]) -> Option<Result<Val, String>> {
+/// Binds the arguments of a call to `function` to its
+/// parameters: positional arguments in order, then keyword
+/// arguments, given with the span of their keyword, by
+/// name. Parameters left over take their default, evaluated
+/// by `default` with the parameters bound before them, so
+/// that a default can refer to earlier parameters.
+fn bind_args<T, F>(
+    function: &str,
+    params: &[FnParam],
+    args: Vec<T>,
+    keyword_args: Vec<(&str, T, Span)>,
+    mut default: F,
+) -> Result<Vec<(String, T)>, Error>
+where
+    F: FnMut(&[(String, T)], &Datum) -> Result<T, Error>,
+{
+    if args.len() > params.len() {
+        return Err(Error::Arity {
+            function: function.to_string(),
+            expected: params.len(),
+            found:    args.len(),
+        });
+    }
+
+    let mut given: Vec<Option<T>> = args.into_iter().map(Some).collect();
+    given.resize_with(params.len(), || None);
+    for (keyword, val, span) in keyword_args {
+        let argument_error = |reason| {
+            Error::Argument {
+                function: function.to_string(),
+                name: keyword.to_string(),
+                reason,
+            }
+            .at(span)
+        };
+        let index = params
+            .iter()
+            .position(|(name, _)| name == keyword)
+            .ok_or_else(|| argument_error("is not a parameter"))?;
+        if given[index].is_some() {
+            return Err(argument_error("is given more than once"));
+        }
+        given[index] = Some(val);
+    }
+
+    let mut bound = Vec::with_capacity(params.len());
+    for ((name, fallback), val) in params.iter().zip(given) {
+        let val = match (val, fallback) {
+            (Some(val), _) => val,
+            (None, Some(fallback)) => default(&bound, fallback)?,
+            (None, None) => {
+                return Err(Error::Argument {
+                    function: function.to_string(),
+                    name:     name.to_string(),
+                    reason:   "is required, but was not given",
+                })
+            },
+        };
+        bound.push((name.to_string(), val));
+    }
+    Ok(bound)
+}
+
+fn builtin(name: &str, args: &[Val]) -> Option<Result<Val, Error>> {
     let result: fn(Vec<f64>) -> Val = match name {
         "+" => |n| Val::Number(n.into_iter().sum()),
         "-" => |n| {
@@ -354,26 +794,38 @@ fn builtin(name: &str, args: &[Val]) -> Option<Result<Val, String>> {
 fn subst(
     graph: &mut ShaderGraph,
     env: &mut Env,
-    form: &Value,
-    source: String,
-) -> Result<String, String> {
+    form: Ref<'_>,
+    mut source: ShaderSource,
+) -> Result<ShaderSource, Error> {
     let mut subst_iter = into_iter(form)?;
     let op = next_symbol(&mut subst_iter)?;
 
     let (name, subst) = match op {
         "define" => {
-            let name =
-                expr(graph, env, next_item(&mut subst_iter)?)?.to_string()?;
-            let val =
-                expr(graph, env, next_item(&mut subst_iter)?)?.to_string()?;
+            let name = expr_as(
+                graph,
+                env,
+                next_item(&mut subst_iter)?,
+                Val::to_string,
+            )?;
+            let val = expr_as(
+                graph,
+                env,
+                next_item(&mut subst_iter)?,
+                Val::to_string,
+            )?;
             let subst = format!("#define {} {}", name, val);
             (name, subst)
         },
         "ifdef" => {
-            let name =
-                expr(graph, env, next_item(&mut subst_iter)?)?.to_string()?;
+            let name = expr_as(
+                graph,
+                env,
+                next_item(&mut subst_iter)?,
+                Val::to_string,
+            )?;
             let should_define =
-                expr(graph, env, next_item(&mut subst_iter)?)?.to_bool()?;
+                expr_as(graph, env, next_item(&mut subst_iter)?, Val::to_bool)?;
             if should_define {
                 (name.clone(), format!("#define {} 1", name))
             } else {
@@ -381,10 +833,152 @@ fn subst(
             }
         },
         other => {
-            return Err(format!("Invalid param substitution type `{}`", other));
+            return Err(Error::Syntax(format!(
+                "Invalid param substitution type `{}`",
+                other
+            )));
         },
     };
 
     iter_finish(subst_iter)?;
-    return Ok(source.replace(&format!("<{}>", name), &subst));
+    source.replace(&format!("<{}>", name), &subst);
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datum(text: &str) -> Datum {
+        Parser::from_str(text).expect_datum().unwrap()
+    }
+
+    /// `(define (f a (b a) (c 3)) ...)`
+    fn params() -> Vec<FnParam> {
+        vec![
+            ("a".to_string(), None),
+            ("b".to_string(), Some(datum("a"))),
+            ("c".to_string(), Some(datum("3"))),
+        ]
+    }
+
+    /// Evaluates a default that is a number, or the name of
+    /// an earlier parameter.
+    fn default(
+        earlier: &[(String, f64)],
+        default: &Datum,
+    ) -> Result<f64, Error> {
+        let value = default.value();
+        if let Some(number) = value.as_f64() {
+            return Ok(number);
+        }
+        let name = value.as_symbol().unwrap();
+        earlier
+            .iter()
+            .find(|(earlier, _)| earlier == name)
+            .map(|(_, val)| *val)
+            .ok_or_else(|| Error::Undefined(name.to_string()))
+    }
+
+    fn keyword(name: &str, val: f64) -> (&str, f64, Span) {
+        (name, val, Span {
+            start: (1, 10),
+            end:   (1, 12),
+        })
+    }
+
+    fn bind(
+        args: Vec<f64>,
+        keyword_args: Vec<(&str, f64, Span)>,
+    ) -> Result<Vec<f64>, String> {
+        bind_args("f", &params(), args, keyword_args, default)
+            .map(|bound| {
+                let names: Vec<&str> =
+                    bound.iter().map(|(name, _)| name.as_str()).collect();
+                assert_eq!(names, ["a", "b", "c"]);
+                bound.into_iter().map(|(_, val)| val).collect()
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn positional_then_keyword() {
+        assert_eq!(bind(vec![1.0, 2.0, 4.0], vec![]), Ok(vec![1.0, 2.0, 4.0]));
+        assert_eq!(
+            bind(vec![1.0], vec![keyword("c", 4.0)]),
+            Ok(vec![1.0, 1.0, 4.0])
+        );
+        assert_eq!(
+            bind(vec![], vec![keyword("c", 4.0), keyword("a", 1.0)]),
+            Ok(vec![1.0, 1.0, 4.0])
+        );
+    }
+
+    #[test]
+    fn defaults_refer_to_earlier_params() {
+        assert_eq!(bind(vec![5.0], vec![]), Ok(vec![5.0, 5.0, 3.0]));
+        assert_eq!(
+            bind(vec![], vec![keyword("a", 7.0)]),
+            Ok(vec![7.0, 7.0, 3.0])
+        );
+    }
+
+    #[test]
+    fn default_errors_are_passed_on() {
+        let params =
+            vec![("a".to_string(), Some(datum("b"))), ("b".to_string(), None)];
+        let error =
+            bind_args("f", &params, vec![], vec![keyword("b", 1.0)], default)
+                .unwrap_err();
+        assert_eq!(error.to_string(), "Item `b` is not defined");
+    }
+
+    #[test]
+    fn missing_required_param() {
+        assert_eq!(
+            bind(vec![], vec![keyword("b", 2.0)]),
+            Err("Argument `a` of `f` is required, but was not given"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_keyword() {
+        let error = bind(vec![1.0], vec![keyword("d", 2.0)]).unwrap_err();
+        assert!(error.starts_with("error: Argument `d` of `f` is not a parameter\n --> <graph>:1:11"), "{}", error);
+    }
+
+    #[test]
+    fn duplicate_keyword() {
+        let error = bind(vec![1.0], vec![keyword("a", 2.0)]).unwrap_err();
+        assert!(
+            error.starts_with(
+                "error: Argument `a` of `f` is given more than once\n"
+            ),
+            "{}",
+            error
+        );
+        let error = bind(vec![], vec![keyword("b", 2.0), keyword("b", 3.0)])
+            .unwrap_err();
+        assert!(
+            error.starts_with(
+                "error: Argument `b` of `f` is given more than once\n"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn too_many_args() {
+        assert_eq!(
+            bind(vec![1.0, 2.0, 3.0, 4.0], vec![]),
+            Err(Error::Arity {
+                function: "f".to_string(),
+                expected: 3,
+                found:    4,
+            }
+            .to_string())
+        );
+    }
 }
@@ -1,62 +1,84 @@
-use crate::graph::NodeId;
+use crate::{
+    graph::{
+        Edge,
+        NodeId,
+    },
+    Error,
+};
 
 #[derive(Debug, Clone)]
 pub enum Val {
     Node(NodeId),
+    /// A node, along with how to sample it when used as an
+    /// input.
+    Sampled(Edge),
     Number(f64),
     Bool(bool),
     String(String),
 }
 
 impl Val {
-    pub fn to_node(&self) -> Result<NodeId, String> {
+    pub fn to_node(&self) -> Result<NodeId, Error> {
         match self {
             Val::Node(n) => Ok(*n),
-            other => Err(format!(
-                "Type mismatch: expected a Node, found `{:?}`",
-                other
-            )),
+            other => Err(Error::TypeMismatch {
+                expected: "Node",
+                found:    format!("{:?}", other),
+            }),
         }
     }
 
-    pub fn to_nat(&self) -> Result<usize, String> {
+    /// Nodes can be used as inputs to shaders, with or
+    /// without sampling options.
+    pub fn to_edge(&self) -> Result<Edge, Error> {
+        match self {
+            Val::Node(n) => Ok((*n).into()),
+            Val::Sampled(edge) => Ok(edge.clone()),
+            other => Err(Error::TypeMismatch {
+                expected: "Node",
+                found:    format!("{:?}", other),
+            }),
+        }
+    }
+
+    pub fn to_nat(&self) -> Result<usize, Error> {
         match self {
             Val::Number(u) => Ok(*u as usize),
-            other => Err(format!(
-                "Type mismatch: expected a Number, found `{:?}`",
-                other
-            )),
+            other => Err(Error::TypeMismatch {
+                expected: "Number",
+                found:    format!("{:?}", other),
+            }),
         }
     }
 
-    pub fn to_float(&self) -> Result<f64, String> {
+    pub fn to_float(&self) -> Result<f64, Error> {
         match self {
             Val::Number(u) => Ok(*u),
-            other => Err(format!(
-                "Type mismatch: expected a Number, found `{:?}`",
-                other
-            )),
+            other => Err(Error::TypeMismatch {
+                expected: "Number",
+                found:    format!("{:?}", other),
+            }),
         }
     }
 
-    pub fn to_string(&self) -> Result<String, String> {
+    pub fn to_string(&self) -> Result<String, Error> {
         match self {
             Val::String(s) => Ok(s.to_string()),
             Val::Number(f) => Ok(format!("{}", f)),
-            other => Err(format!(
-                "Type mismatch: expected a String, found `{:?}`",
-                other
-            )),
+            other => Err(Error::TypeMismatch {
+                expected: "String",
+                found:    format!("{:?}", other),
+            }),
         }
     }
 
-    pub fn to_bool(&self) -> Result<bool, String> {
+    pub fn to_bool(&self) -> Result<bool, Error> {
         match self {
             Val::Bool(b) => Ok(*b),
-            other => Err(format!(
-                "Type mismatch: expected a Boolean, found `{:?}`",
-                other
-            )),
+            other => Err(Error::TypeMismatch {
+                expected: "Boolean",
+                found:    format!("{:?}", other),
+            }),
         }
     }
 }
@@ -2,7 +2,20 @@ use std::{
     collections::BTreeMap,
     ffi::OsStr,
     fs,
-    path::PathBuf,
+    io::{
+        self,
+        BufRead,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+    sync::mpsc::{
+        self,
+        Receiver,
+    },
+    thread,
     time::{
         Duration,
         Instant,
@@ -10,28 +23,51 @@ use std::{
 };
 
 use glium::{
-    backend::Facade,
+    backend::{
+        Context,
+        Facade,
+    },
     glutin::{
         event::{
+            ElementState,
             Event,
+            KeyboardInput,
+            VirtualKeyCode,
             WindowEvent,
         },
         event_loop::ControlFlow,
     },
+    Display,
     Surface,
+    Texture2d,
 };
 use shadergarden::{
+    golden::GoldenTest,
+    graph::{
+        Clock,
+        NodeId,
+        ShaderGraph,
+    },
     lisp,
     map,
     png,
     reload,
-    util,
+    util::{
+        self,
+        Inspect,
+    },
 };
 use structopt::{
     clap::AppSettings,
     StructOpt,
 };
 
+/// The number of nodes listed when printing a profile.
+const PROFILE_NODES: usize = 10;
+
+/// How often the profile is printed while running.
+const PROFILE_EVERY: Duration = Duration::from_secs(5);
+
 /// Waits a specific number of nanoseconds before rendering
 /// the next frame. Returns a control_flow that should be
 /// set in the event loop. This is based on the current
@@ -53,6 +89,356 @@ pub fn handle_event(event: Event<()>, control_flow: &mut ControlFlow) {
     }
 }
 
+/// Returns the key that was pressed, if the event is a key
+/// press.
+fn pressed_key(event: &Event<()>) -> Option<VirtualKeyCode> {
+    match event {
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => *virtual_keycode,
+        _ => None,
+    }
+}
+
+/// Lists the graph's outputs in the order they were marked,
+/// alongside the name each was marked with. Bails if the
+/// graph has no outputs.
+fn outputs(graph: &ShaderGraph) -> Vec<(NodeId, String)> {
+    let outputs = graph.get_labeled_outputs();
+
+    if outputs.is_empty() {
+        eprintln!("[fatal] Graph has no outputs.");
+        panic!();
+    }
+
+    outputs
+}
+
+/// Writes a frame of each output to disk. A single output
+/// is written directly to `dir`; with several outputs, each
+/// is written to its own subdirectory named after the
+/// output.
+fn write_frames(
+    output_map: &BTreeMap<NodeId, &Texture2d>,
+    outputs: &[(NodeId, String)],
+    dir: &Path,
+    frame: u64,
+) {
+    let file_name = format!("frame-{:0>4}.png", frame);
+
+    if let [(id, _)] = outputs {
+        png::write_png(output_map[id], &dir.join(file_name));
+        return;
+    }
+
+    for (id, name) in outputs {
+        let output_dir = dir.join(name);
+        fs::create_dir_all(&output_dir)
+            .expect("Could not create output directory");
+        png::write_png(output_map[id], &output_dir.join(&file_name));
+    }
+}
+
+/// Turns on profiling, warning if it's not supported.
+fn start_profiling(graph: &mut ShaderGraph, enabled: bool) {
+    if let Err(e) = graph.set_profiling(enabled) {
+        eprintln!("[warn] {}", e);
+    }
+}
+
+/// Prints the nodes that took longest on the GPU, if the
+/// graph has been profiled.
+fn print_profile(graph: &ShaderGraph) {
+    let profile = match graph.get_profile() {
+        Some(profile) if profile.frames > 0 => profile,
+        _ => return,
+    };
+
+    eprintln!(
+        "[prof] {:.3} ms per frame on the GPU, over {} frames",
+        profile.mean_frame_ms(),
+        profile.frames
+    );
+    eprintln!(
+        "[prof] {:>9} {:>9} {:>9}  node",
+        "mean ms", "max ms", "pixels"
+    );
+
+    let slowest = profile.slowest();
+    for (id, timing) in slowest.iter().take(PROFILE_NODES) {
+        let info = graph.get_node_info(*id);
+        let mut label = info.label();
+        if !info.names.is_empty() {
+            label = format!("{} ({})", label, info.names.join(", "));
+        }
+        eprintln!(
+            "[prof] {:>9.3} {:>9.3} {:>9}  {}",
+            timing.mean_ms(),
+            timing.max_ms,
+            timing.pixels,
+            label
+        );
+    }
+    if slowest.len() > PROFILE_NODES {
+        eprintln!("[prof] ... and {} more", slowest.len() - PROFILE_NODES);
+    }
+}
+
+/// Joins things into a list for printing, e.g. `a, b`.
+fn list<T: std::fmt::Display>(items: impl Iterator<Item = T>) -> String {
+    let items: Vec<String> = items.map(|item| format!("`{}`", item)).collect();
+    if items.is_empty() {
+        "nothing".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+/// Shows a path relative to the project, if it's inside it.
+fn relative(path: &Path, project: &Path) -> String {
+    path.strip_prefix(project)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Prints how much memory a graph's textures take up.
+fn print_memory(graph: &ShaderGraph) {
+    let memory = graph.texture_memory();
+    let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    eprintln!(
+        "[info] Textures take up {:.1} MiB ({:.1} MiB before pooling)",
+        mib(memory.allocated),
+        mib(memory.unpooled)
+    );
+}
+
+/// Warns about shaders reading uniforms the graph doesn't
+/// provide, or not reading their inputs.
+fn print_uniform_warnings(graph: &ShaderGraph) {
+    for mismatch in graph.check_uniforms() {
+        eprintln!("[warn] {}", mismatch);
+    }
+}
+
+/// Parses a channel name, e.g. `r` or `alpha`, into the
+/// index of that channel.
+fn parse_channel(channel: &str) -> Result<usize, String> {
+    match channel {
+        "r" | "red" | "0" => Ok(0),
+        "g" | "green" | "1" => Ok(1),
+        "b" | "blue" | "2" => Ok(2),
+        "a" | "alpha" | "3" => Ok(3),
+        other => Err(format!("`{}` is not a channel (r, g, b, a)", other)),
+    }
+}
+
+/// Parses a frame rate, which must be a positive number.
+fn parse_fps(fps: &str) -> Result<f64, String> {
+    match fps.parse::<f64>() {
+        Ok(fps) if fps.is_finite() && fps > 0.0 => Ok(fps),
+        Ok(_) => Err(format!("`{}` is not a positive frame rate", fps)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Reads debug commands from stdin on a separate thread, so
+/// they can be handled without blocking the event loop.
+fn console() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let sent = line.map(|line| sender.send(line).is_ok());
+            if sent.ok() != Some(true) {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Tracks which outputs are shown in the window: either a
+/// single selected output, or all of them tiled in a grid.
+/// Any named node can also be tapped for debugging, in
+/// which case it is shown instead of the outputs.
+struct OutputView {
+    selected: usize,
+    grid:     bool,
+    tap:      Option<String>,
+    inspect:  Inspect,
+}
+
+impl OutputView {
+    fn new(args: &Run) -> OutputView {
+        let range = match args.range.as_slice() {
+            [min, max] => (*min, *max),
+            _ => Inspect::default().range,
+        };
+
+        OutputView {
+            selected: 0,
+            grid:     false,
+            tap:      args.tap.to_owned(),
+            inspect:  Inspect {
+                channel: args.channel,
+                range,
+            },
+        }
+    }
+
+    /// Handles a debug command typed into the console.
+    fn command(&mut self, line: &str, graph: &mut ShaderGraph) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["tap", name] => {
+                if graph.get_node(name).is_none() {
+                    eprintln!("[warn] No node named `{}`, try `nodes`", name);
+                    return;
+                }
+                eprintln!("[info] Tapping node `{}`", name);
+                self.tap = Some(name.to_string());
+            },
+            ["untap"] => {
+                eprintln!("[info] Showing outputs");
+                self.tap = None;
+            },
+            ["channel", "all"] => self.inspect.channel = None,
+            ["channel", channel] => match parse_channel(channel) {
+                Ok(channel) => self.inspect.channel = Some(channel),
+                Err(e) => eprintln!("[warn] {}", e),
+            },
+            ["range", min, max] => match (min.parse(), max.parse()) {
+                (Ok(min), Ok(max)) => self.inspect.range = (min, max),
+                _ => eprintln!("[warn] Range must be two numbers"),
+            },
+            ["nodes"] => {
+                let names: Vec<&String> =
+                    graph.get_named_nodes().keys().collect();
+                eprintln!("[info] Nodes: {:?}", names);
+            },
+            ["params"] => {
+                for (name, param) in graph.get_params() {
+                    eprintln!(
+                        "[info] {} {} = {:?}",
+                        param.glsl_type(),
+                        name,
+                        param.components()
+                    );
+                }
+            },
+            ["set", name, values @ ..] => {
+                let values = values.iter().map(|v| v.parse()).collect::<Result<
+                    Vec<f32>,
+                    _,
+                >>(
+                );
+                let param = graph.get_param(name);
+                match (param, values) {
+                    (None, _) => {
+                        eprintln!("[warn] No parameter named `{}`", name)
+                    },
+                    (_, Err(e)) => eprintln!("[warn] {}", e),
+                    (Some(param), Ok(values)) => {
+                        match param.with_components(&values) {
+                            // unwrap: the parameter exists with this type
+                            Some(value) => {
+                                graph.set_param(name, value).unwrap()
+                            },
+                            None => eprintln!(
+                                "[warn] Expected {} values for a {}",
+                                param.components().len(),
+                                param.glsl_type()
+                            ),
+                        }
+                    },
+                }
+            },
+            [] => (),
+            _ => eprintln!(
+                "[warn] Commands: tap <name>, untap, nodes, \
+                 channel <r|g|b|a|all>, range <min> <max>, \
+                 params, set <name> <values...>"
+            ),
+        }
+    }
+
+    /// The tapped node, if it exists in the current graph
+    /// and has a texture to show.
+    fn tapped(&self, graph: &ShaderGraph) -> Option<NodeId> {
+        let id = graph.get_node(self.tap.as_ref()?)?;
+        graph.get_texture(id)?;
+        Some(id)
+    }
+
+    /// Draws a tapped node's texture.
+    fn draw_tap(&self, display: &Display, texture: &Texture2d) {
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        util::inspect_texture(display, &mut target, texture, self.inspect);
+        target.finish().unwrap();
+    }
+
+    /// Tab and the arrow keys cycle through outputs, `G`
+    /// toggles the grid view.
+    fn handle_key(
+        &mut self,
+        key: VirtualKeyCode,
+        outputs: &[(NodeId, String)],
+    ) {
+        let count = outputs.len();
+        match key {
+            VirtualKeyCode::Tab | VirtualKeyCode::Right => {
+                self.selected = (self.selected + 1) % count;
+            },
+            VirtualKeyCode::Left => {
+                self.selected = (self.selected + count - 1) % count;
+            },
+            VirtualKeyCode::G => {
+                self.grid = !self.grid;
+                if self.grid {
+                    eprintln!("[info] Showing all {} outputs", count);
+                    return;
+                }
+            },
+            _ => return,
+        }
+        eprintln!("[info] Showing output `{}`", outputs[self.selected].1);
+    }
+
+    /// Draws the outputs according to the current view.
+    fn draw(
+        &mut self,
+        display: &Display,
+        output_map: &BTreeMap<NodeId, &Texture2d>,
+        outputs: &[(NodeId, String)],
+    ) {
+        // the graph may have been rebuilt with fewer outputs
+        self.selected %= outputs.len();
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        if self.grid {
+            let textures: Vec<&Texture2d> =
+                outputs.iter().map(|(id, _)| output_map[id]).collect();
+            util::texture_grid(display, &mut target, &textures);
+        } else {
+            let (id, _) = &outputs[self.selected];
+            util::texture(display, &mut target, output_map[id]);
+        }
+        target.finish().unwrap();
+    }
+}
+
 pub fn dir(path: &OsStr) -> PathBuf {
     if path == "." {
         std::env::current_dir().expect("Can not determine package directory")
@@ -87,22 +473,85 @@ struct Run {
     height:  u32,
     #[structopt(short, long)]
     inputs:  Vec<PathBuf>,
+    /// Show the node bound to this variable, instead of the
+    /// outputs
+    #[structopt(long)]
+    tap:     Option<String>,
+    /// Only show this channel of the tapped node: r, g, b,
+    /// or a
+    #[structopt(long, parse(try_from_str = parse_channel))]
+    channel: Option<usize>,
+    /// Map this range of the tapped node's values to black
+    /// and white
+    #[structopt(long, number_of_values = 2, allow_hyphen_values = true)]
+    range:   Vec<f32>,
+    /// Time each node on the GPU, and print the slowest
+    #[structopt(long)]
+    profile: bool,
 }
 
 #[derive(StructOpt, Debug)]
 struct Render {
     #[structopt(flatten)]
-    run:    Run,
+    run:      Run,
     #[structopt(short, long)]
-    output: PathBuf,
+    output:   PathBuf,
     /// Starting frame
     #[structopt(short, long, default_value = "0")]
-    start:  u64,
+    start:    u64,
     /// Ending frame
     #[structopt(short, long, default_value = "150")]
-    end:    u64,
-    #[structopt(long, default_value = "30")]
-    fps:    f64,
+    end:      u64,
+    #[structopt(long, default_value = "30", parse(try_from_str = parse_fps))]
+    fps:      f64,
+    /// Render offscreen, without opening a window
+    #[structopt(long)]
+    headless: bool,
+}
+
+/// Compares frames of a graph against golden images
+#[derive(StructOpt, Debug)]
+struct Test {
+    #[structopt(default_value = ".", parse(from_os_str = package_dir))]
+    project:   PathBuf,
+    #[structopt(short, long)]
+    graph:     Option<PathBuf>,
+    /// Frames to compare
+    #[structopt(short, long, default_value = "0")]
+    frames:    Vec<u64>,
+    #[structopt(long, default_value = "30", parse(try_from_str = parse_fps))]
+    fps:       f64,
+    /// Largest difference allowed in any channel of any
+    /// pixel
+    #[structopt(short, long, default_value = "0")]
+    tolerance: u8,
+    /// Directory of golden images [default:
+    /// <project>/golden]
+    #[structopt(long)]
+    golden:    Option<PathBuf>,
+    /// Directory to write the frames that don't match, and
+    /// diffs of them [default: <project>/golden-diff]
+    #[structopt(long)]
+    diffs:     Option<PathBuf>,
+    /// Store the frames as the new golden images, rather
+    /// than comparing them
+    #[structopt(long)]
+    bless:     bool,
+}
+
+/// Prints the nodes of a graph, to inspect or visualize it
+#[derive(StructOpt, Debug)]
+struct Graph {
+    #[structopt(default_value = ".", parse(from_os_str = package_dir))]
+    project: PathBuf,
+    #[structopt(short, long)]
+    graph:   Option<PathBuf>,
+    /// Print the graph in Graphviz's DOT language
+    #[structopt(long, conflicts_with = "json")]
+    dot:     bool,
+    /// Print the graph as JSON
+    #[structopt(long)]
+    json:    bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -111,6 +560,8 @@ enum Cli {
     New(New),
     Run(Run),
     Render(Render),
+    Test(Test),
+    Graph(Graph),
 }
 
 /// Main function
@@ -124,6 +575,8 @@ fn main() {
     match args {
         Cli::Run(r) => run(r),
         Cli::Render(r) => render(r),
+        Cli::Test(t) => test(t),
+        Cli::Graph(g) => graph(g),
         Cli::New(n) => new(n.project),
     }
 }
@@ -150,13 +603,134 @@ fn new(path: PathBuf) {
 
 // TODO: factor out common parts of render and run
 
+/// Where `test` keeps golden images and diffs of a project
+/// by default. These are skipped when loading shaders.
+fn golden_dirs(project: &Path) -> Vec<PathBuf> {
+    vec![project.join("golden"), project.join("golden-diff")]
+}
+
+/// Loads the project's shaders and builds the initial graph
+/// within the given context, bailing if either fails.
+/// Shaders aren't loaded from the directories in `exclude`,
+/// which hold output.
+fn load_graph(
+    context: &Rc<Context>,
+    project: &Path,
+    graph: &Option<PathBuf>,
+    exclude: &[PathBuf],
+) -> ShaderGraph {
+    let lisp_config = graph
+        .to_owned()
+        .unwrap_or_else(|| project.join("shader.graph"));
+
+    let shader_dir = reload::ShaderDir::new_from_dir_excluding(
+        project,
+        &lisp_config,
+        exclude,
+    )
+    .expect("Could not load initial shader directory");
+    let graph = lisp::graph_from_sexp(context, shader_dir, map! {})
+        .map_err(|e| {
+            eprintln!("[fatal] Could not build initial graph:");
+            eprintln!("{}", e);
+            panic!();
+        })
+        .unwrap();
+    print_uniform_warnings(&graph);
+    print_memory(&graph);
+    graph
+}
+
+/// Renders frames without a window, driving the graph in a
+/// plain loop rather than an event loop.
+fn render_headless(render: Render) {
+    let args = render.run;
+    let inputs = &args.inputs;
+
+    let headless = util::create_headless(args.width, args.height)
+        .map_err(|e| {
+            eprintln!("[fatal] {}", e);
+            panic!();
+        })
+        .unwrap();
+    let mut exclude = golden_dirs(&args.project);
+    exclude.push(render.output.to_owned());
+    let mut graph = load_graph(
+        headless.get_context(),
+        &args.project,
+        &args.graph,
+        &exclude,
+    );
+
+    eprintln!("[info] Built initial graph");
+
+    // build a table of textures
+    #[cfg(feature = "ffmpeg")]
+    let mut input_textures =
+        util::input_textures(&headless, inputs, args.width, args.height);
+
+    #[cfg(not(feature = "ffmpeg"))]
+    assert!(
+        inputs.is_empty(),
+        "Inputs are not supported when running without ffmpeg"
+    );
+
+    eprintln!("[info] Starting Headless Render...");
+    start_profiling(&mut graph, args.profile);
+
+    // step exactly one frame at a time, so renders are
+    // reproducible
+    graph.set_clock(Clock::fixed(render.fps));
+
+    for frame_number in 0..=render.end {
+        // get the input and output handles
+        let input_nodes = graph.get_inputs().to_owned();
+        let outputs = outputs(&graph);
+
+        #[cfg(not(feature = "ffmpeg"))]
+        assert!(input_nodes.is_empty());
+
+        #[cfg(feature = "ffmpeg")]
+        assert_eq!(
+            input_nodes.len(),
+            input_textures.len(),
+            "The number of graph inputs and provided textures does not match up",
+        );
+
+        #[allow(unused_mut)]
+        let mut input_map = BTreeMap::new();
+
+        #[cfg(feature = "ffmpeg")]
+        for (node_id, texture) in
+            input_nodes.iter().zip(input_textures.iter_mut())
+        {
+            input_map.insert(*node_id, texture.next_frame());
+        }
+
+        let output_map = graph.forward(input_map);
+
+        if frame_number >= render.start {
+            write_frames(
+                &output_map,
+                &outputs,
+                &render.output,
+                frame_number - render.start,
+            );
+        }
+    }
+
+    print_profile(&graph);
+    eprintln!("[info] Finished render");
+}
+
 fn render(render: Render) {
+    if render.headless {
+        render_headless(render);
+        return;
+    }
+
     let args = render.run;
-    let lisp_config = args
-        .graph
-        .to_owned()
-        .unwrap_or_else(|| args.project.join("shader.graph"));
-    let inputs = args.inputs;
+    let inputs = args.inputs.to_owned();
 
     // set up the main event loop
     let (event_loop, display) = util::create(
@@ -165,17 +739,10 @@ fn render(render: Render) {
         args.height as f64,
     );
 
-    // set up hot code reloading
-    let shader_dir = reload::ShaderDir::new_from_dir(args.project, lisp_config)
-        .expect("Could not load initial shader directory");
+    let mut exclude = golden_dirs(&args.project);
+    exclude.push(render.output.to_owned());
     let mut graph =
-        lisp::graph_from_sexp(display.get_context(), shader_dir, map! {})
-            .map_err(|e| {
-                eprintln!("[fatal] Could not build initial graph:");
-                eprintln!("{}", e);
-                panic!();
-            })
-            .unwrap();
+        load_graph(display.get_context(), &args.project, &args.graph, &exclude);
 
     eprintln!("[info] Built initial graph");
 
@@ -185,7 +752,10 @@ fn render(render: Render) {
         util::input_textures(&display, &inputs, args.width, args.height);
 
     #[cfg(not(feature = "ffmpeg"))]
-    assert!(inputs.is_empty(), "Inputs are not supported when running without ffmpeg");
+    assert!(
+        inputs.is_empty(),
+        "Inputs are not supported when running without ffmpeg"
+    );
 
     eprintln!("[info] Starting Render...");
 
@@ -193,21 +763,18 @@ fn render(render: Render) {
     let frames_output = render.output;
     let frame_start = render.start;
     let frame_end = render.end;
-    let frame_nanos = (1000000000.0 / render.fps) as u64;
+    let mut view = OutputView::new(&args);
+    graph.set_clock(Clock::fixed(render.fps));
+    start_profiling(&mut graph, args.profile);
 
-    event_loop.run(move |event, _, mut control_flow| {
+    event_loop.run(move |event, _, control_flow| {
         // waits until next frame, keep at top
         *control_flow = wait_nanos(0);
-        handle_event(event, &mut control_flow);
+        handle_event(event, control_flow);
 
         // get the input and output handles
         let input_nodes = graph.get_inputs();
-        let output = if let [output] = graph.get_outputs().as_slice() {
-            *output
-        } else {
-            eprintln!("[fatal] Graph has invalid output signature.");
-            panic!();
-        };
+        let outputs = outputs(&graph);
 
         #[cfg(not(feature = "ffmpeg"))]
         assert!(input_nodes.is_empty());
@@ -228,36 +795,147 @@ fn render(render: Render) {
             input_map.insert(*node_id, texture.next_frame());
         }
 
-        // dumb hack to make the playback smooth(er)
-        graph.created = std::time::Instant::now()
-            - std::time::Duration::from_nanos(frame_nanos * frame_number);
+        let tapped = view.tapped(&graph);
+        graph.set_evaluate_all(tapped.is_some());
         let output_map = graph.forward(input_map);
 
-        // set up the draw target and draw
-        let mut target = display.draw();
-        target.clear_color(0.0, 0.0, 0.0, 1.0);
-        let texture = output_map[&output];
-        util::texture(&display, &mut target, texture);
-        target.finish().unwrap();
-
         if frame_number >= frame_start {
-            png::write_png(texture, &frames_output.join(
-                format!("frame-{:0>4}.png", frame_number - frame_start)
-            ));
+            write_frames(
+                &output_map,
+                &outputs,
+                &frames_output,
+                frame_number - frame_start,
+            );
+        }
+
+        // set up the draw target and draw
+        match tapped {
+            // unwrap: tapped nodes always have a texture
+            Some(id) => view.draw_tap(&display, graph.get_texture(id).unwrap()),
+            None => view.draw(&display, &output_map, &outputs),
         }
         if frame_number > frame_end {
+            print_profile(&graph);
             panic!("Finished render, bailing pathetically");
         }
         frame_number += 1
     });
 }
 
+/// Renders a graph offscreen, and compares it against
+/// golden images. Exits with an error if any frame doesn't
+/// match.
+fn test(args: Test) {
+    // only the graph's own textures are rendered to, so the
+    // context can be tiny
+    let headless = util::create_headless(1, 1)
+        .map_err(|e| {
+            eprintln!("[fatal] {}", e);
+            panic!();
+        })
+        .unwrap();
+
+    let project = args.project;
+    let golden = args.golden.unwrap_or_else(|| project.join("golden"));
+    let diffs = args.diffs.unwrap_or_else(|| project.join("golden-diff"));
+    let mut exclude = golden_dirs(&project);
+    exclude.extend([golden.to_owned(), diffs.to_owned()]);
+
+    let mut graph =
+        load_graph(headless.get_context(), &project, &args.graph, &exclude);
+    if !graph.get_inputs().is_empty() {
+        eprintln!("[fatal] Graphs with inputs can not be tested");
+        panic!();
+    }
+    let golden_test = GoldenTest {
+        frames: args.frames,
+        fps: args.fps,
+        tolerance: args.tolerance,
+        golden,
+        diffs: Some(diffs),
+    };
+
+    if args.bless {
+        let written = golden_test
+            .bless(&mut graph, &BTreeMap::new())
+            .unwrap_or_else(|e| {
+                eprintln!("[fatal] {}", e);
+                panic!();
+            });
+        for path in written {
+            eprintln!("[info] Wrote {}", path.display());
+        }
+        return;
+    }
+
+    let comparisons = golden_test
+        .run(&mut graph, &BTreeMap::new())
+        .unwrap_or_else(|e| {
+            eprintln!("[fatal] {}", e);
+            panic!();
+        });
+
+    let mut failed = 0;
+    for comparison in comparisons.iter() {
+        let name =
+            format!("`{}` frame {}", comparison.output, comparison.frame);
+        match &comparison.failure {
+            None => eprintln!("[info] {} ok", name),
+            Some(failure) => {
+                eprintln!("[fail] {}: {}", name, failure);
+                failed += 1;
+            },
+        }
+    }
+
+    if failed > 0 {
+        eprintln!(
+            "[fatal] {} of {} frames did not match, see {}",
+            failed,
+            comparisons.len(),
+            // unwrap: always set above
+            golden_test.diffs.unwrap().display()
+        );
+        std::process::exit(1);
+    }
+    eprintln!("[info] All {} frames match", comparisons.len());
+}
+
+/// Builds a graph offscreen, and prints its nodes.
+fn graph(args: Graph) {
+    // nothing is rendered, so the context can be tiny
+    let headless = util::create_headless(1, 1)
+        .map_err(|e| {
+            eprintln!("[fatal] {}", e);
+            panic!();
+        })
+        .unwrap();
+    let graph = load_graph(
+        headless.get_context(),
+        &args.project,
+        &args.graph,
+        &golden_dirs(&args.project),
+    );
+
+    if args.dot {
+        print!("{}", graph.to_dot());
+    } else if args.json {
+        print!("{}", graph.to_json());
+    } else {
+        for info in graph.iter_nodes() {
+            println!("{}", info);
+        }
+    }
+}
+
 fn run(args: Run) {
     let lisp_config = args
         .graph
         .to_owned()
         .unwrap_or_else(|| args.project.join("shader.graph"));
-    let inputs = args.inputs;
+    let inputs = args.inputs.to_owned();
+    let profile = args.profile;
+    let mut view = OutputView::new(&args);
 
     // set up the main event loop
     let (event_loop, display) = util::create(
@@ -267,10 +945,12 @@ fn run(args: Run) {
     );
 
     // set up hot code reloading
-    let mut watcher = reload::ShaderGraphWatcher::new_watch_dir(
+    let project = args.project.to_owned();
+    let mut watcher = reload::ShaderGraphWatcher::new_watch_dir_excluding(
         display.get_context(),
-        args.project,
-        lisp_config,
+        &args.project,
+        &lisp_config,
+        golden_dirs(&args.project),
     )
     .map_err(|e| {
         eprintln!("[fatal] Could not build initial graph:");
@@ -279,6 +959,8 @@ fn run(args: Run) {
     })
     .unwrap();
     eprintln!("[info] Built initial graph");
+    print_uniform_warnings(watcher.graph_no_reload());
+    print_memory(watcher.graph_no_reload());
 
     // build a table of textures
     #[cfg(feature = "ffmpeg")]
@@ -286,34 +968,56 @@ fn run(args: Run) {
         util::input_textures(&display, &inputs, args.width, args.height);
 
     #[cfg(not(feature = "ffmpeg"))]
-    assert!(inputs.is_empty(), "Inputs are not supported when running without ffmpeg");
+    assert!(
+        inputs.is_empty(),
+        "Inputs are not supported when running without ffmpeg"
+    );
 
     eprintln!("[info] Starting...");
 
-    event_loop.run(move |event, _, mut control_flow| {
+    let commands = console();
+    let mut mouse = util::Mouse::new();
+    let mut keyboard = util::Keyboard::new();
+    start_profiling(watcher.graph_no_reload(), profile);
+    let mut last_profile = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
         // waits until next frame, keep at top
         *control_flow = wait_nanos(16_666_667);
-        handle_event(event, &mut control_flow);
+        let key = pressed_key(&event);
+        mouse.handle_event(&event, display.gl_window().window().inner_size());
+        keyboard.handle_event(&event);
+        handle_event(event, control_flow);
 
         // get the graph, notify if updated
         let (graph, watch_result) = watcher.graph();
         match watch_result {
             reload::WatchResult::NoChange => (),
-            reload::WatchResult::Rebuilt => eprintln!("[info] Graph rebuilt"),
+            reload::WatchResult::Rebuilt { changed, recompiled } => {
+                eprintln!(
+                    "[info] Graph rebuilt, after changes to {}",
+                    list(changed.iter().map(|path| relative(path, &project)))
+                );
+                eprintln!("[info] Recompiled {}", list(recompiled.iter()));
+                print_uniform_warnings(graph);
+                start_profiling(graph, profile);
+            },
             reload::WatchResult::Err(e) => {
                 eprintln!("[warn] Could not rebuild graph:");
                 eprintln!("{}", e);
             }
         }
 
+        for command in commands.try_iter() {
+            view.command(&command, graph);
+        }
+
         // get the input and output handles
         let input_nodes = graph.get_inputs();
-        let output = if let [output] = graph.get_outputs().as_slice() {
-            *output
-        } else {
-            eprintln!("[fatal] Graph has invalid output signature.");
-            panic!();
-        };
+        let outputs = outputs(graph);
+        if let Some(key) = key {
+            view.handle_key(key, &outputs);
+        }
 
         #[cfg(not(feature = "ffmpeg"))]
         assert!(input_nodes.is_empty());
@@ -334,12 +1038,42 @@ fn run(args: Run) {
             input_map.insert(*node_id, texture.next_frame());
         }
 
+        graph.set_mouse(mouse.next_frame());
+        if let Err(e) = graph.set_keyboard(&keyboard.next_frame()) {
+            eprintln!("[warn] {}", e);
+        }
+        let tapped = view.tapped(graph);
+        // tapped nodes might not contribute to an output
+        graph.set_evaluate_all(tapped.is_some());
         let output_map = graph.forward(input_map);
 
         // set up the draw target and draw
-        let mut target = display.draw();
-        target.clear_color(0.0, 0.0, 0.0, 1.0);
-        util::texture(&display, &mut target, output_map[&output]);
-        target.finish().unwrap();
+        match tapped {
+            // unwrap: tapped nodes always have a texture
+            Some(id) => view.draw_tap(&display, graph.get_texture(id).unwrap()),
+            None => view.draw(&display, &output_map, &outputs),
+        }
+
+        // report on the last few seconds of frames
+        if profile && last_profile.elapsed() >= PROFILE_EVERY {
+            print_profile(graph);
+            graph.reset_profile();
+            last_profile = Instant::now();
+        }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fps_must_be_positive() {
+        assert_eq!(parse_fps("30"), Ok(30.0));
+        assert_eq!(parse_fps("0.5"), Ok(0.5));
+        for fps in ["0", "-30", "nan", "inf"] {
+            assert!(parse_fps(fps).is_err(), "{}", fps);
+        }
+        assert!(parse_fps("fast").is_err());
+    }
+}
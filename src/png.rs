@@ -1,4 +1,6 @@
 use std::{
+    fs,
+    io,
     io::Cursor,
     path::Path,
 };
@@ -9,40 +11,68 @@ use glium::texture::{
 };
 use image::{
     ImageBuffer,
+    ImageError,
     ImageFormat,
     Rgba,
 };
 
+use crate::Error;
+
 /// Pretty neat macro right here. Takes an image path, loads
 /// it, and converts it to a raw_image.
 #[macro_export]
 macro_rules! include_png {
     ($path:literal) => {{
         let bytes = include_bytes!($path);
-        crate::png::image_from_bytes(bytes)
+        $crate::png::image_from_bytes(bytes)
     }};
 }
 
 pub fn image_from_bytes<'a>(bytes: Vec<u8>) -> RawImage2d<'a, u8> {
-    let cursor = Cursor::new(&bytes);
-    let image = image::load(cursor, ImageFormat::Png).unwrap().to_rgba8();
+    decode(&bytes).unwrap()
+}
+
+fn decode<'a>(bytes: &[u8]) -> Result<RawImage2d<'a, u8>, ImageError> {
+    let cursor = Cursor::new(bytes);
+    let image = image::load(cursor, ImageFormat::Png)?.to_rgba8();
     let image_dimensions = image.dimensions();
 
-    RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions)
+    Ok(RawImage2d::from_raw_rgba_reversed(
+        &image.into_raw(),
+        image_dimensions,
+    ))
 }
 
-pub fn load_png(path: &Path) -> RawImage2d<u8> {
-    let bytes = std::fs::read(path).expect("Could not read input image");
-    image_from_bytes(bytes)
+pub fn load_png(path: &Path) -> RawImage2d<'_, u8> {
+    read_png(path).expect("Could not read input image")
 }
 
-pub fn write_png(texture: &Texture2d, path: &Path) {
-    let mut buffer =
-        ImageBuffer::new(texture.width() as u32, texture.height() as u32);
+/// Like `load_png`, but returns an error if the file can't
+/// be read or isn't a valid PNG.
+pub fn read_png<'a>(path: &Path) -> Result<RawImage2d<'a, u8>, Error> {
+    let bytes = fs::read(path).map_err(|error| Error::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    decode(&bytes).map_err(|error| image_error(path, error))
+}
 
+pub fn write_png(texture: &Texture2d, path: &Path) {
     let sink: Vec<Vec<(u8, u8, u8, u8)>> = texture.read();
+    write_pixels(&sink, path).expect("Could not write frame");
+    println!("Saved frame {}", path.display());
+}
+
+/// Writes rows of RGBA pixels, bottom row first, as read
+/// from a texture.
+pub fn write_pixels(
+    pixels: &[Vec<(u8, u8, u8, u8)>],
+    path: &Path,
+) -> Result<(), Error> {
+    let width = pixels.first().map(|row| row.len()).unwrap_or(0);
+    let mut buffer = ImageBuffer::new(width as u32, pixels.len() as u32);
 
-    for (y, row) in sink.iter().rev().enumerate() {
+    for (y, row) in pixels.iter().rev().enumerate() {
         for (x, pixel) in row.iter().enumerate() {
             buffer.put_pixel(
                 x as u32,
@@ -52,6 +82,16 @@ pub fn write_png(texture: &Texture2d, path: &Path) {
         }
     }
 
-    buffer.save(&path).expect("Could not write frame");
-    println!("Saved frame {}", path.display());
+    buffer.save(path).map_err(|error| image_error(path, error))
+}
+
+fn image_error(path: &Path, error: ImageError) -> Error {
+    let error = match error {
+        ImageError::IoError(error) => error,
+        other => io::Error::new(io::ErrorKind::InvalidData, other),
+    };
+    Error::Io {
+        path: path.to_path_buf(),
+        error,
+    }
 }
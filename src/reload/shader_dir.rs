@@ -2,7 +2,10 @@ use std::{
     collections::BTreeMap,
     ffi::OsStr,
     fs,
-    path::Path,
+    path::{
+        Path,
+        PathBuf,
+    },
 };
 
 use include_dir::{
@@ -10,17 +13,33 @@ use include_dir::{
     Dir,
 };
 
+use crate::{
+    lisp::{
+        is_hidden,
+        load_files,
+        shader_name,
+    },
+    Error,
+};
+
 pub const BASE_PROJECT: Dir = include_dir!("./demos/base");
 
 /// Represents a directory of shaders, and a shader graph
 /// lisp configuration file.
 pub struct ShaderDir {
-    pub lisp:    String,
-    pub shaders: BTreeMap<String, String>,
+    pub lisp:      String,
+    pub shaders:   BTreeMap<String, String>,
+    /// Code shared between shaders, from `.glsl` files,
+    /// which can only be `#include`d.
+    pub libraries: BTreeMap<String, String>,
+    /// Path to the lisp configuration file, if it was read
+    /// from disk. Used when reporting errors.
+    pub config:    Option<PathBuf>,
 }
 
 impl ShaderDir {
-    /// Creates a new `ShaderDir` from component parts.
+    /// Creates a new `ShaderDir` from component parts,
+    /// without any libraries.
     pub fn new(
         lisp_graph: String,
         shaders: BTreeMap<String, String>,
@@ -28,82 +47,104 @@ impl ShaderDir {
         ShaderDir {
             lisp: lisp_graph,
             shaders,
+            libraries: BTreeMap::new(),
+            config: None,
         }
     }
 
-    // TODO: abstract and combine following new methods
-
     /// Creates a `ShaderDir` from a directory included at
     /// compile time. Note that `lisp_graph` must be a
-    /// parsable lisp expression, not a path.
+    /// parsable lisp expression, not a path. Shaders in
+    /// subdirectories are named by their path, as in
+    /// [`load_shaders`], as are libraries.
+    ///
+    /// [`load_shaders`]: crate::lisp::load_shaders
     pub fn new_from_included(
         dir: Dir,
         lisp_graph: String,
-    ) -> Result<ShaderDir, String> {
+    ) -> Result<ShaderDir, Error> {
         let mut shaders = BTreeMap::new();
+        ShaderDir::insert_included(dir.path(), dir, "frag", &mut shaders)?;
+        let mut libraries = BTreeMap::new();
+        ShaderDir::insert_included(dir.path(), dir, "glsl", &mut libraries)?;
+
+        Ok(ShaderDir {
+            lisp: lisp_graph,
+            shaders,
+            libraries,
+            config: None,
+        })
+    }
+
+    fn insert_included(
+        root: &Path,
+        dir: Dir,
+        extension: &str,
+        map: &mut BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        for sub_dir in dir.dirs() {
+            if !is_hidden(sub_dir.path()) {
+                ShaderDir::insert_included(root, *sub_dir, extension, map)?;
+            }
+        }
+
         for file in dir.files() {
-            if file.path().is_dir()
-                || file.path().extension() != Some(OsStr::new("frag"))
+            if is_hidden(file.path())
+                || file.path().extension() != Some(OsStr::new(extension))
             {
                 continue;
             }
 
             // get the key and value, insertomundo!
-            let name = file
-                .path()
-                .file_stem()
-                .ok_or("Could not infer shader name from file name")?
-                .to_str()
-                .ok_or("Could not convert file name to UTF8 string")?
-                .to_string();
+            // included paths are relative to the root of the
+            // inclusion, which need not be `root`
+            let relative =
+                file.path().strip_prefix(root).unwrap_or(file.path());
+            let name = shader_name(relative)?;
             let contents = String::from_utf8(file.contents().to_vec())
-                .map_err(|_| "Could not get shader contents")?;
-            shaders.insert(name, contents);
+                .map_err(|_| Error::InvalidPath(file.path().to_path_buf()))?;
+            map.insert(name, contents);
         }
 
-        Ok(ShaderDir {
-            lisp: lisp_graph,
-            shaders,
-        })
+        Ok(())
     }
 
-    /// Creates a new `ShaderDir` from a directory.
-    pub fn new_from_dir<T>(path: T, config: T) -> Result<ShaderDir, String>
+    /// Creates a new `ShaderDir` from a directory, and its
+    /// subdirectories, see [`load_shaders`] and
+    /// [`load_libraries`].
+    ///
+    /// [`load_shaders`]: crate::lisp::load_shaders
+    /// [`load_libraries`]: crate::lisp::load_libraries
+    pub fn new_from_dir<T>(path: T, config: T) -> Result<ShaderDir, Error>
     where
         T: AsRef<Path>,
     {
-        let lisp = fs::read_to_string(&config).map_err(|_| {
-            format!(
-                "Could not read `{}` in shader directory",
-                config.as_ref().to_str().unwrap()
-            )
-        })?;
-
-        let mut shaders = BTreeMap::new();
-        let files = fs::read_dir(path)
-            .map_err(|_| "Could not read shader directory".to_string())?;
-
-        for p in files {
-            // some type stuff, you know the deal
-            let path = p.map_err(|_| "Got a bad file path".to_string())?.path();
-
-            // only include `.frag` files
-            if path.is_dir() || path.extension() != Some(OsStr::new("frag")) {
-                continue;
-            }
+        ShaderDir::new_from_dir_excluding(path, config, &[])
+    }
 
-            // get the key and value, insertomundo!
-            let name = path
-                .file_stem()
-                .ok_or("Could not infer shader name from file name")?
-                .to_str()
-                .ok_or("Could not convert file name to UTF8 string")?
-                .to_string();
-            let contents = fs::read_to_string(path)
-                .map_err(|_| "Could not get shader contents")?;
-            shaders.insert(name, contents);
-        }
+    /// Like `new_from_dir`, but skips the directories in
+    /// `exclude`, e.g. where frames are rendered to, so
+    /// they aren't walked every time the graph is loaded.
+    pub fn new_from_dir_excluding<T>(
+        path: T,
+        config: T,
+        exclude: &[PathBuf],
+    ) -> Result<ShaderDir, Error>
+    where
+        T: AsRef<Path>,
+    {
+        let lisp = fs::read_to_string(&config).map_err(|error| Error::Io {
+            path: config.as_ref().to_path_buf(),
+            error,
+        })?;
+        let shaders = load_files(path.as_ref(), "frag", exclude)?;
+        let libraries = load_files(path.as_ref(), "glsl", exclude)?;
 
-        Ok(ShaderDir { lisp, shaders })
+        Ok(ShaderDir {
+            lisp,
+            shaders,
+            libraries,
+            config: Some(config.as_ref().to_path_buf()),
+        })
     }
 }
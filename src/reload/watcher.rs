@@ -1,15 +1,14 @@
 use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
     path::{
         Path,
         PathBuf,
     },
     rc::Rc,
-    sync::{
-        atomic::{
-            AtomicBool,
-            Ordering,
-        },
-        Arc,
+    sync::mpsc::{
+        self,
+        Receiver,
     },
     time::{
         Duration,
@@ -19,6 +18,7 @@ use std::{
 
 use glium::backend::Context;
 use notify::{
+    Event,
     RecommendedWatcher,
     RecursiveMode,
     Watcher,
@@ -26,20 +26,37 @@ use notify::{
 
 use crate::{
     graph::ShaderGraph,
-    lisp::graph_from_sexp,
+    lisp::{
+        graph_from_sexp,
+        regraph_from_sexp,
+    },
     map,
     reload::ShaderDir,
+    Error,
 };
 
+/// How long to wait after the last change before
+/// rebuilding, so that a save that touches several files,
+/// or writes a file in several steps, is seen as a whole.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Extensions of the files a graph is built from.
+const WATCHED_EXTENSIONS: &[&str] = &["frag", "glsl", "graph"];
+
 /// A struct that watches a directory for changes,
 /// and hot-reloads a shader graph if changes have been
 /// made.
 pub struct ShaderGraphWatcher {
-    context:      Rc<Context>,
-    last_reload:  Instant,
     path:         PathBuf,
     config:       PathBuf,
-    changed:      Arc<AtomicBool>,
+    /// Directories skipped when loading shaders.
+    exclude:      Vec<PathBuf>,
+    /// Changed files, as reported by the watcher thread.
+    events:       Receiver<PathBuf>,
+    /// Files changed since the last rebuild.
+    changed:      BTreeSet<PathBuf>,
+    /// When the last change was seen.
+    last_change:  Option<Instant>,
     _watcher:     RecommendedWatcher,
     shader_graph: ShaderGraph,
 }
@@ -48,10 +65,44 @@ pub enum WatchResult {
     /// No changes were made.
     NoChange,
     /// Changes were made and the graph was rebuilt.
-    Rebuilt,
+    Rebuilt {
+        /// The files that changed.
+        changed:    Vec<PathBuf>,
+        /// The shaders that had to be compiled again, by
+        /// name. Unchanged shaders are reused.
+        recompiled: Vec<String>,
+    },
     /// Changes were made but the graph could not be
     /// rebuilt.
-    Err(String),
+    Err(Error),
+}
+
+/// Whether a change to a file could change the graph.
+/// Hidden files, like editor swap files and lock files,
+/// and anything in a hidden or excluded directory, are
+/// ignored, as are files like rendered frames.
+fn is_watched(
+    path: &Path,
+    root: &Path,
+    config: &Path,
+    exclude: &[PathBuf],
+) -> bool {
+    if exclude.iter().any(|dir| path.starts_with(dir)) {
+        return false;
+    }
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let hidden = relative.iter().any(|component| {
+        component
+            .to_str()
+            .map(|name| name.starts_with('.') || name.starts_with('#'))
+            .unwrap_or(true)
+    });
+    let extension = path.extension().and_then(OsStr::to_str);
+    let watched = extension
+        .map(|extension| WATCHED_EXTENSIONS.contains(&extension))
+        .unwrap_or(false);
+
+    path == config || (watched && !hidden)
 }
 
 impl ShaderGraphWatcher {
@@ -62,34 +113,75 @@ impl ShaderGraphWatcher {
         context: &Rc<Context>,
         path: T,
         config: T,
-    ) -> Result<ShaderGraphWatcher, String>
+    ) -> Result<ShaderGraphWatcher, Error>
+    where
+        T: AsRef<Path>,
+    {
+        ShaderGraphWatcher::new_watch_dir_excluding(
+            context,
+            path,
+            config,
+            vec![],
+        )
+    }
+
+    /// Like `new_watch_dir`, but skips the directories in
+    /// `exclude` when loading shaders, see
+    /// `ShaderDir::new_from_dir_excluding`, and ignores
+    /// changes within them.
+    pub fn new_watch_dir_excluding<T>(
+        context: &Rc<Context>,
+        path: T,
+        config: T,
+        exclude: Vec<PathBuf>,
+    ) -> Result<ShaderGraphWatcher, Error>
     where
         T: AsRef<Path>,
     {
         let path = path.as_ref().to_path_buf();
         let config = config.as_ref().to_path_buf();
+        // events are reported with absolute paths
+        let absolute_path =
+            path.canonicalize().unwrap_or_else(|_| path.clone());
+        let absolute_config =
+            config.canonicalize().unwrap_or_else(|_| config.clone());
+        let absolute_exclude: Vec<PathBuf> = exclude
+            .iter()
+            .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+            .collect();
 
-        let changed = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
         // build the watcher
-        let mut watcher = RecommendedWatcher::new({
-            let changed = changed.clone();
-            move |res| match res {
-                Ok(_) => changed.store(true, Ordering::SeqCst),
+        let mut watcher = RecommendedWatcher::new(
+            move |res: notify::Result<Event>| match res {
+                Ok(event) => {
+                    for path in event.paths {
+                        if is_watched(
+                            &path,
+                            &absolute_path,
+                            &absolute_config,
+                            &absolute_exclude,
+                        ) {
+                            let _ = sender.send(path);
+                        }
+                    }
+                },
                 Err(e) => println!("[warn] Watch error: `{:?}`.", e),
-            }
-        })
+            },
+        )
         .unwrap();
         watcher.watch(&path, RecursiveMode::Recursive).unwrap();
 
-        let shader_graph = ShaderGraphWatcher::build(context, &path, &config)?;
-        let last_reload = Instant::now();
+        let shader_graph =
+            ShaderGraphWatcher::build(context, &path, &config, &exclude)?;
 
         Ok(ShaderGraphWatcher {
-            context: context.clone(),
-            last_reload,
             path,
             config,
-            changed,
+            exclude,
+            events,
+            changed: BTreeSet::new(),
+            last_change: None,
             _watcher: watcher,
             shader_graph,
         })
@@ -99,12 +191,23 @@ impl ShaderGraphWatcher {
         context: &Rc<Context>,
         path: &Path,
         config: &Path,
-    ) -> Result<ShaderGraph, String> {
-        let shader_dir = ShaderDir::new_from_dir(path, config)?;
+        exclude: &[PathBuf],
+    ) -> Result<ShaderGraph, Error> {
+        let shader_dir =
+            ShaderDir::new_from_dir_excluding(path, config, exclude)?;
         let shader_graph = graph_from_sexp(context, shader_dir, map! {})?;
         Ok(shader_graph)
     }
 
+    fn rebuild(&self) -> Result<ShaderGraph, Error> {
+        let shader_dir = ShaderDir::new_from_dir_excluding(
+            &self.path,
+            &self.config,
+            &self.exclude,
+        )?;
+        regraph_from_sexp(&self.shader_graph, shader_dir, map! {})
+    }
+
     /// Gets the shader graph without trying to reload
     /// Note that `graph` will only reload when needed,
     /// And tries to de-duplicate redundant reloads,
@@ -117,35 +220,123 @@ impl ShaderGraphWatcher {
     /// Forces a rebuild of the graph. Do not call this in a
     /// loop! As with `graph_no_reload`, only use this
     /// for fine-grained control over reloads.
+    /// Unchanged shaders aren't recompiled, and recurrent
+    /// shaders keep their state where possible.
     pub fn graph_force_reload(&mut self) -> (&mut ShaderGraph, WatchResult) {
-        let watch_result = match ShaderGraphWatcher::build(
-            &self.context,
-            &self.path,
-            &self.config,
-        ) {
+        self.receive();
+        let changed = std::mem::take(&mut self.changed).into_iter().collect();
+        self.last_change = None;
+
+        let watch_result = match self.rebuild() {
             Ok(graph) => {
+                let recompiled = graph.get_compiled().to_owned();
                 self.shader_graph = graph;
-                WatchResult::Rebuilt
+                WatchResult::Rebuilt {
+                    changed,
+                    recompiled,
+                }
             },
             Err(error) => WatchResult::Err(error),
         };
 
-        self.last_reload = Instant::now();
         (&mut self.shader_graph, watch_result)
     }
 
-    /// Reloads a shader graph if there have been changes,
-    /// And the graph hasn't been rebuilt recently.
+    /// Collects changes reported by the watcher thread.
+    fn receive(&mut self) {
+        for path in self.events.try_iter() {
+            self.changed.insert(path);
+            self.last_change = Some(Instant::now());
+        }
+    }
+
+    /// Reloads a shader graph once relevant files have
+    /// changed, and then stayed the same for a moment.
     /// Note that if compilation fails, the old graph will
     /// remain in use. Returns a borrowed `ShaderGraph`,
     /// and whether the graph was rebuilt.
     pub fn graph(&mut self) -> (&mut ShaderGraph, WatchResult) {
-        if self.last_reload.elapsed() > Duration::from_millis(300)
-            && self.changed.swap(false, Ordering::SeqCst)
-        {
+        self.receive();
+        let settled = self
+            .last_change
+            .map(|last_change| last_change.elapsed() >= DEBOUNCE)
+            .unwrap_or(false);
+
+        if settled {
             self.graph_force_reload()
         } else {
             (self.graph_no_reload(), WatchResult::NoChange)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watched(path: &str) -> bool {
+        is_watched(
+            Path::new(path),
+            Path::new("/project"),
+            Path::new("/project/shader.graph"),
+            &[PathBuf::from("/project/frames")],
+        )
+    }
+
+    #[test]
+    fn graph_files_are_watched() {
+        assert!(watched("/project/shader.graph"));
+        assert!(watched("/project/blur.frag"));
+        assert!(watched("/project/lib/noise.glsl"));
+        assert!(watched("/project/other.graph"));
+    }
+
+    #[test]
+    fn other_extensions_are_ignored() {
+        assert!(!watched("/project/frame-0001.png"));
+        assert!(!watched("/project/blur.frag~"));
+        assert!(!watched("/project/blur.frag.swp"));
+        assert!(!watched("/project/README"));
+    }
+
+    #[test]
+    fn hidden_files_are_ignored() {
+        assert!(!watched("/project/.blur.frag"));
+        assert!(!watched("/project/.git/shader.graph"));
+        assert!(!watched("/project/lib/.noise.glsl"));
+    }
+
+    #[test]
+    fn autosave_files_are_ignored() {
+        assert!(!watched("/project/#blur.frag#"));
+        assert!(!watched("/project/.#blur.frag"));
+        assert!(!watched("/project/lib/#noise.glsl"));
+    }
+
+    #[test]
+    fn hidden_root_is_watched() {
+        // only components within the project count
+        assert!(is_watched(
+            Path::new("/home/.config/project/blur.frag"),
+            Path::new("/home/.config/project"),
+            Path::new("/home/.config/project/shader.graph"),
+            &[],
+        ));
+    }
+
+    #[test]
+    fn config_is_watched_whatever_its_name() {
+        let config = Path::new("/project/.graph");
+        assert!(is_watched(config, Path::new("/project"), config, &[]));
+        let config = Path::new("/elsewhere/scene.lisp");
+        assert!(is_watched(config, Path::new("/project"), config, &[]));
+    }
+
+    #[test]
+    fn excluded_directories_are_ignored() {
+        assert!(!watched("/project/frames/stale.frag"));
+        assert!(!watched("/project/frames/nested/stale.glsl"));
+        // only whole components are compared
+        assert!(watched("/project/frames2/blur.frag"));
+    }
+}
@@ -0,0 +1,186 @@
+use glium::glutin::{
+    dpi::PhysicalSize,
+    event::{
+        ElementState,
+        Event,
+        KeyboardInput,
+        MouseButton,
+        VirtualKeyCode,
+        WindowEvent,
+    },
+};
+
+/// Tracks the mouse in the same way as Shadertoy's
+/// `iMouse`, so it can be passed to a graph with
+/// `ShaderGraph::set_mouse`. Positions are from 0 to 1,
+/// with the bottom-left being the origin.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Mouse {
+    cursor:  [f32; 2],
+    drag:    [f32; 2],
+    click:   [f32; 2],
+    down:    bool,
+    clicked: bool,
+}
+
+impl Mouse {
+    pub fn new() -> Mouse { Default::default() }
+
+    /// Updates the mouse from a window event. `size` is the
+    /// size of the window the event came from.
+    pub fn handle_event(&mut self, event: &Event<()>, size: PhysicalSize<u32>) {
+        let event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return,
+        };
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let (width, height) = (size.width.max(1), size.height.max(1));
+                self.cursor = [
+                    position.x as f32 / width as f32,
+                    1.0 - position.y as f32 / height as f32,
+                ];
+                if self.down {
+                    self.drag = self.cursor;
+                }
+            },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.down = *state == ElementState::Pressed;
+                if self.down {
+                    self.clicked = true;
+                    self.click = self.cursor;
+                    self.drag = self.cursor;
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// The mouse for the next frame: `xy` is where the
+    /// mouse was last dragged, and `zw` is where it was
+    /// last clicked. `z` is negative when the button is
+    /// up, and `w` is negative except on the frame of
+    /// the click.
+    pub fn next_frame(&mut self) -> [f32; 4] {
+        let [x, y] = self.click;
+        let z = if self.down { x } else { -x };
+        let w = if self.clicked { y } else { -y };
+        self.clicked = false;
+        [self.drag[0], self.drag[1], z, w]
+    }
+}
+
+/// Tracks the keyboard in the same layout as Shadertoy's
+/// keyboard texture, so it can be passed to a graph with
+/// `ShaderGraph::set_keyboard`. Each column is a key, by
+/// its javascript key code. Row 0 is 255 while the key is
+/// held, row 1 is 255 on the frame it was pressed, and row
+/// 2 toggles between 0 and 255 on each press.
+pub struct Keyboard {
+    state: [[u8; 256]; 3],
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard {
+            state: [[0; 256]; 3],
+        }
+    }
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard { Default::default() }
+
+    /// Updates the keyboard from a window event.
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        let (state, key) = match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => (state, key),
+            _ => return,
+        };
+
+        let code = match key_code(*key) {
+            Some(code) => code as usize,
+            None => return,
+        };
+
+        match state {
+            ElementState::Pressed => {
+                // ignore key repeats
+                if self.state[0][code] == 0 {
+                    self.state[1][code] = 255;
+                    self.state[2][code] ^= 255;
+                }
+                self.state[0][code] = 255;
+            },
+            ElementState::Released => self.state[0][code] = 0,
+        }
+    }
+
+    /// The keyboard for the next frame.
+    pub fn next_frame(&mut self) -> [[u8; 256]; 3] {
+        let state = self.state;
+        self.state[1] = [0; 256];
+        state
+    }
+}
+
+/// Converts a key to its javascript key code, as used by
+/// Shadertoy.
+fn key_code(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X,
+        Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] =
+        [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const FUNCTIONS: [VirtualKeyCode; 12] =
+        [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+    let offset = |keys: &[VirtualKeyCode], start: u8| {
+        keys.iter().position(|k| *k == key).map(|i| start + i as u8)
+    };
+
+    offset(&LETTERS, 65)
+        .or_else(|| offset(&DIGITS, 48))
+        .or_else(|| offset(&FUNCTIONS, 112))
+        .or(match key {
+            Back => Some(8),
+            Tab => Some(9),
+            Return => Some(13),
+            LShift | RShift => Some(16),
+            LControl | RControl => Some(17),
+            LAlt | RAlt => Some(18),
+            Escape => Some(27),
+            Space => Some(32),
+            PageUp => Some(33),
+            PageDown => Some(34),
+            End => Some(35),
+            Home => Some(36),
+            Left => Some(37),
+            Up => Some(38),
+            Right => Some(39),
+            Down => Some(40),
+            Insert => Some(45),
+            Delete => Some(46),
+            _ => None,
+        })
+}
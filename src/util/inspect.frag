@@ -0,0 +1,18 @@
+#version 140
+
+in vec2 coords;
+out vec4 color;
+
+uniform sampler2D tex;
+uniform int channel;
+uniform vec2 range;
+
+void main() {
+    vec4 value = (texture(tex, coords) - range.x) / (range.y - range.x);
+
+    if (channel >= 0) {
+        color = vec4(vec3(value[channel]), 1.0);
+    } else {
+        color = value;
+    }
+}
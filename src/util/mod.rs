@@ -8,13 +8,22 @@ use glium::{
         Facade,
     },
     glutin::{
-        dpi::LogicalSize,
+        dpi::{
+            LogicalSize,
+            PhysicalSize,
+        },
         event_loop::EventLoop,
         window::WindowBuilder,
         ContextBuilder,
+        CreationError,
+        NotCurrent,
     },
     implement_vertex,
     index::NoIndices,
+    program::{
+        ProgramCreationError,
+        ShaderType,
+    },
     texture::{
         MipmapsOption,
         UncompressedFloatFormat,
@@ -25,15 +34,29 @@ use glium::{
         Sampler,
     },
     Display,
+    DrawParameters,
     Frame,
+    HeadlessRenderer,
     Program,
+    Rect,
     Surface,
     Texture2d,
     VertexBuffer,
 };
 
+mod devices;
+
+pub use devices::{
+    Keyboard,
+    Mouse,
+};
+
 #[cfg(feature = "ffmpeg")]
 use crate::input::FrameStream;
+use crate::{
+    glsl::ShaderSource,
+    Error,
+};
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
@@ -95,8 +118,68 @@ pub fn create(
     (event_loop, display)
 }
 
+/// Sets up an offscreen context to draw into, without
+/// opening a window. On Linux and the BSDs this is backed
+/// by OSMesa, so it works on machines with no display
+/// server and only a software GL implementation. Note that
+/// `width` and `height` only size the (unused) default
+/// framebuffer; shader nodes own their own textures.
+pub fn create_headless(
+    width: u32,
+    height: u32,
+) -> Result<HeadlessRenderer, Error> {
+    let size = PhysicalSize::new(width, height);
+    let context = build_headless_context(size).map_err(|e| {
+        Error::Context(format!("Unable to create headless context: {}", e))
+    })?;
+    HeadlessRenderer::new(context).map_err(|e| {
+        Error::Context(format!("Headless context is not supported: {}", e))
+    })
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn build_headless_context(
+    size: PhysicalSize<u32>,
+) -> Result<glium::glutin::Context<NotCurrent>, CreationError> {
+    use glium::glutin::platform::unix::HeadlessContextExt;
+    ContextBuilder::new().build_osmesa(size)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn build_headless_context(
+    size: PhysicalSize<u32>,
+) -> Result<glium::glutin::Context<NotCurrent>, CreationError> {
+    // other platforms can't create a context without an event
+    // loop, but they don't need a display server either.
+    let event_loop = EventLoop::new();
+    ContextBuilder::new().build_headless(&event_loop, size)
+}
+
 /// Writes an output texture to the entire window.
 pub fn texture(display: &Display, target: &mut Frame, texture: &Texture2d) {
+    texture_grid(display, target, &[texture]);
+}
+
+/// Writes a number of output textures to the window, tiled
+/// in a grid from the top left, row by row. A single
+/// texture fills the entire window.
+pub fn texture_grid(
+    display: &Display,
+    target: &mut Frame,
+    textures: &[&Texture2d],
+) {
     let rect_strip = RectStrip::new(display);
     let vertex_shader_src = include_str!("./texture.vert");
     let fragment_shader_src = include_str!("./texture.frag");
@@ -109,14 +192,94 @@ pub fn texture(display: &Display, target: &mut Frame, texture: &Texture2d) {
     )
     .unwrap();
 
+    // as square as possible, favoring more columns
+    let columns = (textures.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (textures.len() as u32).div_ceil(columns);
+    let (width, height) = target.get_dimensions();
+    let (cell_width, cell_height) = (width / columns, height / rows.max(1));
+
+    for (index, texture) in textures.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let viewport = Rect {
+            left:   column * cell_width,
+            // viewports start at the bottom left
+            bottom: height - (row + 1) * cell_height,
+            width:  cell_width,
+            height: cell_height,
+        };
+
+        target
+            .draw(
+                &rect_strip.buffer,
+                rect_strip.indices,
+                &program,
+                &uniform! {
+                    tex: Sampler::new(*texture)
+                        .magnify_filter(MagnifySamplerFilter::Nearest),
+                },
+                &DrawParameters {
+                    viewport: Some(viewport),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+    }
+}
+
+/// How to show a texture when inspecting it, e.g. in a
+/// debug view of an intermediate node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inspect {
+    /// Only show this channel (0 through 3, i.e. RGBA), as
+    /// grayscale. `None` shows all channels.
+    pub channel: Option<usize>,
+    /// Values in this range are mapped to the range 0 to 1
+    /// on screen, i.e. black to white.
+    pub range:   (f32, f32),
+}
+
+impl Default for Inspect {
+    fn default() -> Inspect {
+        Inspect {
+            channel: None,
+            range:   (0.0, 1.0),
+        }
+    }
+}
+
+/// Writes a texture to the entire window, isolating
+/// channels and remapping values as specified.
+pub fn inspect_texture(
+    display: &Display,
+    target: &mut Frame,
+    texture: &Texture2d,
+    inspect: Inspect,
+) {
+    let rect_strip = RectStrip::new(display);
+    let vertex_shader_src = include_str!("./texture.vert");
+    let fragment_shader_src = include_str!("./inspect.frag");
+
+    let program = glium::Program::from_source(
+        display,
+        vertex_shader_src,
+        fragment_shader_src,
+        None,
+    )
+    .unwrap();
+
+    let channel = inspect.channel.map(|c| c as i32).unwrap_or(-1);
+    let (min, max) = inspect.range;
+
     target
         .draw(
             &rect_strip.buffer,
-            &rect_strip.indices,
+            rect_strip.indices,
             &program,
             &uniform! {
                 tex: Sampler::new(texture)
                     .magnify_filter(MagnifySamplerFilter::Nearest),
+                channel: channel,
+                range: [min, max],
             },
             &Default::default(),
         )
@@ -128,28 +291,59 @@ pub fn default_buffer(
     width: u32,
     height: u32,
 ) -> Texture2d {
-    // set up the shader and its buffer
-    Texture2d::empty_with_format(
+    format_buffer(
         context,
-        UncompressedFloatFormat::U16U16U16U16, // 4 16-bit color channels
-        MipmapsOption::NoMipmap,
         width,
         height,
+        UncompressedFloatFormat::U16U16U16U16, // 4 16-bit color channels
+        MipmapsOption::NoMipmap,
     )
-    .unwrap()
 }
 
+/// Makes a buffer for a shader to render into, with the
+/// given format.
+pub fn format_buffer(
+    context: &Rc<Context>,
+    width: u32,
+    height: u32,
+    format: UncompressedFloatFormat,
+    mipmaps: MipmapsOption,
+) -> Texture2d {
+    Texture2d::empty_with_format(context, format, mipmaps, width, height)
+        .unwrap()
+}
+
+/// Compiles a fragment shader against the standard vertex
+/// shader. Line numbers in compile errors are mapped back
+/// to the files the source was built from.
 pub fn compile_shader(
     context: &Rc<Context>,
-    source: &str,
-) -> Result<Program, String> {
-    Program::from_source(context, include_str!("./texture.vert"), source, None)
-        .map_err(|e| format!("{}", e))
+    source: &ShaderSource,
+) -> Result<Program, Error> {
+    Program::from_source(
+        context,
+        include_str!("./texture.vert"),
+        &source.text(),
+        None,
+    )
+    .map_err(|e| {
+        let log = match e {
+            ProgramCreationError::CompilationError(
+                log,
+                ShaderType::Fragment,
+            ) => source.remap_log(&log),
+            other => format!("{}", other),
+        };
+        Error::ShaderCompile {
+            shader: source.name().map(str::to_string),
+            log,
+        }
+    })
 }
 
 #[cfg(feature = "ffmpeg")]
-pub fn input_textures(
-    display: &Display,
+pub fn input_textures<F: Facade>(
+    facade: &F,
     inputs: &[PathBuf],
     width: u32,
     height: u32,
@@ -162,9 +356,9 @@ pub fn input_textures(
             texture_path.to_string_lossy()
         );
         input_textures.push(
-            FrameStream::new(&texture_path, width, height, display)
+            FrameStream::new(texture_path, width, height, facade)
                 .expect("Couldn't open frame source"),
         );
     }
-    return input_textures;
+    input_textures
 }